@@ -0,0 +1,302 @@
+// Copyright (c) 2016 multimap developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Recommends the inline capacity `N` of a MultiMap from observed list lengths.
+
+use alloc::vec::Vec;
+use core::cmp;
+use core::mem;
+
+use MultiMap;
+
+/// What `CapacityAdvisor::advise` optimizes for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdvisorGoal {
+    /// Minimize the projected bytes of the keys and value lists.
+    Bytes,
+
+    /// Minimize the projected number of heap allocations for value lists.
+    Allocations,
+}
+
+/// The projected memory use of a MultiMap for one inline capacity `N`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapacityEstimate {
+    /// The inline capacity.
+    pub n: usize,
+
+    /// The bytes taken by the keys and the per-key `SmallVec`s in the hash
+    /// table.
+    pub table_bytes: usize,
+
+    /// The bytes of the heap buffers of spilled keys.
+    pub heap_bytes: usize,
+
+    /// The number of keys whose values spill to the heap.
+    pub spilled_keys: usize,
+
+    /// The number of heap allocations and reallocations made while pushing
+    /// the values one by one.
+    pub allocations: usize,
+}
+
+impl CapacityEstimate {
+    /// Returns the total projected bytes.
+    pub fn total_bytes(&self) -> usize {
+        self.table_bytes + self.heap_bytes
+    }
+}
+
+/// The result of `CapacityAdvisor::advise`: the recommended inline capacity
+/// and the estimates of all candidates.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapacityAdvice {
+    /// The recommended inline capacity.
+    pub recommended: usize,
+
+    /// The estimates of all candidates, in the order they were given.
+    pub candidates: Vec<CapacityEstimate>,
+}
+
+/// A what-if simulator which projects the memory use of a MultiMap for
+/// different inline capacities `N` and recommends one.
+///
+/// The advisor is fed the number of values of each key, either from a map or
+/// from a sample of list lengths. The projection assumes the values of each
+/// key were pushed one at a time, and the `union` layout of `SmallVec` used by
+/// this crate. Padding between keys and values and the hash table's load
+/// factor are not taken into account, so the numbers are best used to compare
+/// candidates rather than as exact sizes.
+///
+/// # Examples
+///
+/// ```
+/// use multimap::{AdvisorGoal, CapacityAdvisor, MultiMap};
+///
+/// let mut map = MultiMap::new();
+/// for i in 0..100u64 {
+///     map.insert(i, i);
+///     map.insert(i, i + 1);
+/// }
+///
+/// let advice = CapacityAdvisor::from_map(&map).advise(1..=4, AdvisorGoal::Allocations);
+/// assert_eq!(advice.recommended, 2);
+/// assert_eq!(advice.candidates[0].allocations, 100);
+/// assert_eq!(advice.candidates[1].allocations, 0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct CapacityAdvisor {
+    key_size: usize,
+    value_size: usize,
+    value_align: usize,
+    length_histogram: Vec<usize>,
+}
+
+impl CapacityAdvisor {
+    /// Creates an advisor for keys and values of the given sizes in bytes.
+    /// The values are assumed to be aligned to their size, up to the
+    /// alignment of `usize`.
+    pub fn new(key_size: usize, value_size: usize) -> CapacityAdvisor {
+        let value_align = if value_size == 0 {
+            1
+        } else {
+            1 << cmp::min(
+                value_size.trailing_zeros(),
+                mem::align_of::<usize>().trailing_zeros(),
+            )
+        };
+        CapacityAdvisor {
+            key_size,
+            value_size,
+            value_align,
+            length_histogram: Vec::new(),
+        }
+    }
+
+    /// Creates an advisor for the key type `K` and value type `V`.
+    pub fn for_types<K, V>() -> CapacityAdvisor {
+        CapacityAdvisor {
+            value_align: mem::align_of::<V>(),
+            ..CapacityAdvisor::new(mem::size_of::<K>(), mem::size_of::<V>())
+        }
+    }
+
+    /// Creates an advisor for the types of a map, fed with the list lengths
+    /// of all of its keys.
+    pub fn from_map<K, V, S, const N: usize>(map: &MultiMap<K, V, S, N>) -> CapacityAdvisor {
+        let mut advisor = CapacityAdvisor::for_types::<K, V>();
+        advisor.length_histogram = map.stats().length_histogram;
+        advisor
+    }
+
+    /// Sets the alignment of values in bytes.
+    pub fn value_align(mut self, value_align: usize) -> CapacityAdvisor {
+        self.value_align = value_align;
+        self
+    }
+
+    /// Records a key with the given number of values.
+    pub fn add_length(&mut self, len: usize) {
+        if self.length_histogram.len() <= len {
+            self.length_histogram.resize(len + 1, 0);
+        }
+        self.length_histogram[len] += 1;
+    }
+
+    /// Records a key for each of the given numbers of values.
+    pub fn add_lengths<I: IntoIterator<Item = usize>>(&mut self, lengths: I) {
+        for len in lengths {
+            self.add_length(len);
+        }
+    }
+
+    /// Returns the projected size of a `SmallVec<[V; n]>`.
+    fn smallvec_size(&self, n: usize) -> usize {
+        let word = mem::size_of::<usize>();
+        let align = cmp::max(self.value_align, mem::align_of::<usize>());
+        let data = cmp::max(n * self.value_size, 2 * word);
+        let data = data.div_ceil(align) * align;
+        (word + data).div_ceil(align) * align
+    }
+
+    /// Projects the memory use for the inline capacity `n`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::CapacityAdvisor;
+    ///
+    /// let mut advisor = CapacityAdvisor::for_types::<u32, u64>();
+    /// advisor.add_lengths(vec![1, 1, 3]);
+    ///
+    /// let estimate = advisor.estimate(2);
+    /// assert_eq!(estimate.spilled_keys, 1);
+    /// assert_eq!(estimate.heap_bytes, 4 * 8);
+    /// ```
+    pub fn estimate(&self, n: usize) -> CapacityEstimate {
+        let keys: usize = self.length_histogram.iter().sum();
+        let mut estimate = CapacityEstimate {
+            n,
+            table_bytes: keys * (self.key_size + self.smallvec_size(n)),
+            heap_bytes: 0,
+            spilled_keys: 0,
+            allocations: 0,
+        };
+
+        for (len, &count) in self.length_histogram.iter().enumerate() {
+            if len <= n || count == 0 {
+                continue;
+            }
+
+            // SmallVec grows to the next power of two above its capacity
+            // whenever a push finds it full.
+            let mut capacity = n;
+            let mut allocations = 0;
+            while capacity < len {
+                capacity = (capacity + 1).next_power_of_two();
+                allocations += 1;
+            }
+
+            estimate.spilled_keys += count;
+            estimate.allocations += count * allocations;
+            estimate.heap_bytes += count * capacity * self.value_size;
+        }
+
+        estimate
+    }
+
+    /// Projects the memory use for each candidate inline capacity and
+    /// recommends the one which best meets the goal. Ties are broken by the
+    /// other measure, and then by the smaller capacity.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no candidates.
+    pub fn advise<I>(&self, candidates: I, goal: AdvisorGoal) -> CapacityAdvice
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let candidates: Vec<_> = candidates.into_iter().map(|n| self.estimate(n)).collect();
+        let best = candidates
+            .iter()
+            .min_by_key(|e| match goal {
+                AdvisorGoal::Bytes => (e.total_bytes(), e.allocations, e.n),
+                AdvisorGoal::Allocations => (e.allocations, e.total_bytes(), e.n),
+            })
+            .expect("no candidate inline capacities");
+
+        CapacityAdvice {
+            recommended: best.n,
+            candidates,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use smallvec::SmallVec;
+
+    #[test]
+    fn smallvec_size_matches_layout() {
+        fn check<V>() {
+            let advisor = CapacityAdvisor::for_types::<u8, V>();
+            assert_eq!(advisor.smallvec_size(0), mem::size_of::<SmallVec<[V; 0]>>());
+            assert_eq!(advisor.smallvec_size(1), mem::size_of::<SmallVec<[V; 1]>>());
+            assert_eq!(advisor.smallvec_size(3), mem::size_of::<SmallVec<[V; 3]>>());
+            assert_eq!(advisor.smallvec_size(8), mem::size_of::<SmallVec<[V; 8]>>());
+        }
+
+        check::<u8>();
+        check::<u16>();
+        check::<[u8; 3]>();
+        check::<u64>();
+        check::<u128>();
+        check::<(u64, u32)>();
+    }
+
+    #[test]
+    fn new_guesses_alignment() {
+        assert_eq!(CapacityAdvisor::new(8, 12).value_align, 4);
+        assert_eq!(CapacityAdvisor::new(8, 3).value_align, 1);
+        assert_eq!(CapacityAdvisor::new(8, 0).value_align, 1);
+    }
+
+    #[test]
+    fn estimate_growth() {
+        let mut advisor = CapacityAdvisor::new(8, 8);
+        advisor.add_lengths(vec![0, 1, 2, 5, 5]);
+
+        let e = advisor.estimate(1);
+        assert_eq!(e.spilled_keys, 3);
+        // 1 -> 2 for the key with two values, 1 -> 2 -> 4 -> 8 for the others.
+        assert_eq!(e.allocations, 1 + 2 * 3);
+        assert_eq!(e.heap_bytes, (2 + 2 * 8) * 8);
+        assert_eq!(e.table_bytes, 5 * (8 + 24));
+
+        let e = advisor.estimate(5);
+        assert_eq!(e.spilled_keys, 0);
+        assert_eq!(e.allocations, 0);
+        assert_eq!(e.heap_bytes, 0);
+    }
+
+    #[test]
+    fn advise_goals() {
+        let mut advisor = CapacityAdvisor::new(8, 8);
+        advisor.add_lengths(vec![1; 99]);
+        advisor.add_length(16);
+
+        let advice = advisor.advise(vec![1, 3, 16], AdvisorGoal::Bytes);
+        assert_eq!(advice.recommended, 1);
+        assert_eq!(advice.candidates.len(), 3);
+
+        let advice = advisor.advise(vec![1, 3, 16], AdvisorGoal::Allocations);
+        assert_eq!(advice.recommended, 16);
+    }
+}
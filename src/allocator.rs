@@ -0,0 +1,391 @@
+// Copyright (c) 2016 multimap developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! A MultiMap which allocates its hash table and spilled values in a custom
+//! allocator.
+
+extern crate allocator_api2;
+
+use core::borrow::Borrow;
+use core::fmt::{self, Debug};
+use core::hash::{BuildHasher, Hash};
+
+use self::allocator_api2::alloc::Allocator;
+use self::allocator_api2::vec::Vec;
+use hashbrown::hash_map::{self, HashMap};
+use smallvec::SmallVec;
+
+/// The values of a key: inline while they fit in `N`, and in a buffer in the
+/// allocator `A` once they spill.
+enum Values<V, A: Allocator, const N: usize> {
+    Inline(SmallVec<[V; N]>),
+    Spilled(Vec<V, A>),
+}
+
+impl<V, A: Allocator + Clone, const N: usize> Values<V, A, N> {
+    fn as_slice(&self) -> &[V] {
+        match self {
+            Values::Inline(values) => values,
+            Values::Spilled(values) => values,
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [V] {
+        match self {
+            Values::Inline(values) => values,
+            Values::Spilled(values) => values,
+        }
+    }
+
+    /// Appends a value, moving the values to a buffer in `alloc` when the
+    /// inline buffer is full, so the `SmallVec` never allocates itself.
+    fn push(&mut self, v: V, alloc: &A) {
+        match self {
+            Values::Inline(values) if values.len() < N => values.push(v),
+            Values::Inline(values) => {
+                let mut spilled = Vec::with_capacity_in((N + 1).next_power_of_two(), alloc.clone());
+                spilled.extend(values.drain(..));
+                spilled.push(v);
+                *self = Values::Spilled(spilled);
+            }
+            Values::Spilled(values) => values.push(v),
+        }
+    }
+
+    fn into_iter(self) -> impl Iterator<Item = V> {
+        let (inline, spilled) = match self {
+            Values::Inline(values) => (Some(values.into_iter()), None),
+            Values::Spilled(values) => (None, Some(values.into_iter())),
+        };
+        inline
+            .into_iter()
+            .flatten()
+            .chain(spilled.into_iter().flatten())
+    }
+}
+
+/// A map which allows storing multiple values per key, with the hash table and
+/// the spilled values allocated in the allocator `A`.
+///
+/// The allocator is any `allocator_api2::alloc::Allocator`, such as a
+/// reference to an arena. Like in MultiMap, up to `N` values of a key are
+/// stored inline in the hash table. When a key gets more values, they are
+/// moved to a buffer in the allocator rather than on the global heap. Dropping
+/// an arena allocator therefore releases all memory of the map at once.
+///
+/// Requires the `allocator-api2` feature.
+///
+/// # Examples
+///
+/// ```
+/// extern crate allocator_api2;
+/// use allocator_api2::alloc::Global;
+/// use multimap::AllocMultiMap;
+/// use std::collections::hash_map::RandomState;
+///
+/// let mut map: AllocMultiMap<_, _, _, _> = AllocMultiMap::with_hasher_in(RandomState::new(), Global);
+/// map.insert("key1", 42);
+/// map.insert("key1", 1337);
+/// assert_eq!(map.get_slice("key1"), Some(&[42, 1337][..]));
+/// ```
+pub struct AllocMultiMap<K, V, S, A: Allocator + Clone, const N: usize = 1> {
+    inner: HashMap<K, Values<V, A, N>, S, A>,
+}
+
+impl<K, V, S, A, const N: usize> AllocMultiMap<K, V, S, A, N>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    /// Creates an empty map which will use the given hash builder to hash keys
+    /// and allocate in the given allocator.
+    pub fn with_hasher_in(hash_builder: S, alloc: A) -> AllocMultiMap<K, V, S, A, N> {
+        AllocMultiMap {
+            inner: HashMap::with_hasher_in(hash_builder, alloc),
+        }
+    }
+
+    /// Creates an empty map with at least the given capacity for keys, which
+    /// will use the given hash builder to hash keys and allocate in the given
+    /// allocator.
+    pub fn with_capacity_and_hasher_in(
+        capacity: usize,
+        hash_builder: S,
+        alloc: A,
+    ) -> AllocMultiMap<K, V, S, A, N> {
+        AllocMultiMap {
+            inner: HashMap::with_capacity_and_hasher_in(capacity, hash_builder, alloc),
+        }
+    }
+
+    /// Returns a reference to the map's allocator.
+    pub fn allocator(&self) -> &A {
+        self.inner.allocator()
+    }
+
+    /// Inserts a key-value pair into the map. If the key already exists, the
+    /// value is appended to its values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate allocator_api2;
+    /// use allocator_api2::alloc::Global;
+    /// use multimap::AllocMultiMap;
+    /// use std::collections::hash_map::RandomState;
+    ///
+    /// let mut map: AllocMultiMap<_, _, _, _> = AllocMultiMap::with_hasher_in(RandomState::new(), Global);
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// assert_eq!(map.get(&1), Some(&42));
+    /// ```
+    pub fn insert(&mut self, k: K, v: V) {
+        let alloc = self.inner.allocator().clone();
+        self.values_mut_or_insert(k).push(v, &alloc);
+    }
+
+    /// Inserts multiple key-value pairs into the map. If the key already
+    /// exists, the values are appended to its values.
+    pub fn insert_many<I: IntoIterator<Item = V>>(&mut self, k: K, v: I) {
+        let alloc = self.inner.allocator().clone();
+        let values = self.values_mut_or_insert(k);
+        for v in v {
+            values.push(v, &alloc);
+        }
+    }
+
+    fn values_mut_or_insert(&mut self, k: K) -> &mut Values<V, A, N> {
+        match self.inner.entry(k) {
+            hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            hash_map::Entry::Vacant(entry) => entry.insert(Values::Inline(SmallVec::new())),
+        }
+    }
+
+    /// Returns true if the map contains the key.
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.inner.contains_key(k)
+    }
+
+    /// Returns the number of keys in the map.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns true if the map contains no keys.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Returns a reference to the first value of the key.
+    pub fn get<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.get_slice(k)?.first()
+    }
+
+    /// Returns the slice of values of the key.
+    pub fn get_slice<Q>(&self, k: &Q) -> Option<&[V]>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.inner.get(k).map(Values::as_slice)
+    }
+
+    /// Returns the mutable slice of values of the key.
+    pub fn get_slice_mut<Q>(&mut self, k: &Q) -> Option<&mut [V]>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.inner.get_mut(k).map(Values::as_mut_slice)
+    }
+
+    /// Removes a key from the map, returning its values if the key was in the
+    /// map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate allocator_api2;
+    /// use allocator_api2::alloc::Global;
+    /// use multimap::AllocMultiMap;
+    /// use std::collections::hash_map::RandomState;
+    ///
+    /// let mut map: AllocMultiMap<_, _, _, _> = AllocMultiMap::with_hasher_in(RandomState::new(), Global);
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// assert_eq!(map.remove(&1).unwrap().collect::<Vec<_>>(), [42, 1337]);
+    /// assert!(map.remove(&1).is_none());
+    /// ```
+    pub fn remove<Q>(&mut self, k: &Q) -> Option<impl Iterator<Item = V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.inner.remove(k).map(Values::into_iter)
+    }
+
+    /// An iterator visiting all keys in arbitrary order.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.inner.keys()
+    }
+
+    /// An iterator visiting all keys in arbitrary order, with the slice of
+    /// values of each key.
+    pub fn iter_all(&self) -> impl Iterator<Item = (&K, &[V])> {
+        self.inner.iter().map(|(k, v)| (k, v.as_slice()))
+    }
+
+    /// Removes all keys and values, keeping the allocated table.
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+}
+
+impl<K, V, S, A, const N: usize> Debug for AllocMultiMap<K, V, S, A, N>
+where
+    K: Eq + Hash + Debug,
+    V: Debug,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter_all()).finish()
+    }
+}
+
+impl<K, V, S, A, const N: usize> PartialEq for AllocMultiMap<K, V, S, A, N>
+where
+    K: Eq + Hash,
+    V: PartialEq,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    fn eq(&self, other: &AllocMultiMap<K, V, S, A, N>) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+
+        self.iter_all()
+            .all(|(key, value)| other.get_slice(key) == Some(value))
+    }
+}
+
+impl<K, V, S, A, const N: usize> Extend<(K, V)> for AllocMultiMap<K, V, S, A, N>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+    A: Allocator + Clone,
+{
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate bumpalo;
+
+    use std::collections::hash_map::RandomState;
+    use std::mem;
+    use std::ops::Range;
+
+    use self::allocator_api2::alloc::Global;
+    use self::bumpalo::Bump;
+    use super::*;
+
+    #[test]
+    fn insert_get_remove() {
+        let mut map: AllocMultiMap<_, _, _, _> =
+            AllocMultiMap::with_capacity_and_hasher_in(4, RandomState::new(), Global);
+        map.extend((0..20).map(|i| (i % 3, i)));
+        map.insert_many(7, vec![]);
+
+        assert_eq!(map.len(), 4);
+        assert_eq!(map.get_slice(&1), Some(&[1, 4, 7, 10, 13, 16, 19][..]));
+        assert_eq!(map.get(&7), None);
+        assert!(map.contains_key(&7));
+
+        map.get_slice_mut(&2).unwrap()[0] = 99;
+        assert_eq!(map.get(&2), Some(&99));
+
+        let removed = map.remove(&0).unwrap();
+        assert_eq!(removed.count(), 7);
+        assert_eq!(map.keys().count(), 3);
+
+        map.clear();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn spilled_values_and_table_in_allocator() {
+        let mut bump = Bump::new();
+        let (table, spilled) = {
+            let mut map: AllocMultiMap<u64, u64, RandomState, &Bump, 2> =
+                AllocMultiMap::with_hasher_in(RandomState::new(), &bump);
+            for k in 0..50 {
+                map.insert_many(k, 0..k % 5);
+            }
+
+            for (k, values) in map.inner.iter() {
+                let spilled = matches!(values, Values::Spilled(_));
+                assert_eq!(spilled, k % 5 > 2);
+            }
+            assert_eq!(map.get_slice(&4), Some(&[0, 1, 2, 3][..]));
+
+            let table: Vec<_> = map.keys().map(|k| k as *const u64 as usize).collect();
+            let spilled: Vec<_> = map
+                .inner
+                .values()
+                .filter(|values| matches!(values, Values::Spilled(_)))
+                .map(|values| values.as_slice().as_ptr() as usize)
+                .collect();
+            // Dropping the map would hand the newest allocations back to the
+            // bump pointer, so keep them in the chunks for the check below.
+            mem::forget(map);
+            (table, spilled)
+        };
+        assert_eq!(table.len(), 50);
+        assert_eq!(spilled.len(), 20);
+
+        let chunks: Vec<Range<usize>> = bump
+            .iter_allocated_chunks()
+            .map(|chunk| {
+                let start = chunk.as_ptr() as usize;
+                start..start + chunk.len()
+            })
+            .collect();
+        for addr in table.iter().chain(&spilled) {
+            assert!(chunks.iter().any(|chunk| chunk.contains(addr)));
+        }
+    }
+
+    #[test]
+    fn eq() {
+        let mut a: AllocMultiMap<_, _, _, _> =
+            AllocMultiMap::with_hasher_in(RandomState::new(), Global);
+        let mut b: AllocMultiMap<_, _, _, _> =
+            AllocMultiMap::with_hasher_in(RandomState::new(), Global);
+        a.insert("k", 1);
+        b.insert("k", 1);
+        assert_eq!(a, b);
+        b.insert("k", 2);
+        assert_ne!(a, b);
+        assert_eq!(format!("{:?}", a), "{\"k\": [1]}");
+    }
+}
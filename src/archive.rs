@@ -0,0 +1,783 @@
+// Copyright (c) 2016 multimap developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! A binary archive format for MultiMaps which is read in place.
+//!
+//! An archive is written from a `MultiMap` or `FrozenMultiMap` whose keys are
+//! byte strings and whose values are fixed size numbers, and is accessed
+//! directly from a byte slice, e.g. a memory-mapped file, with
+//! `ArchivedMultiMap::from_bytes`. Opening an archive validates its structure
+//! once, in a single pass and without allocating; lookups afterwards only read
+//! the bytes of the key's bucket and decode values as they are accessed.
+//!
+//! All integers are stored little-endian. The layout is:
+//!
+//! * a 48 byte header: the magic `MULTIMAP`, the format version and the value
+//!   size as `u32`, then the key count, value count, bucket count and size of
+//!   the key section as `u64`,
+//! * `bucket count + 1` offsets into the entries as `u64`, where the entries of
+//!   bucket `b` are those from offset `b` up to offset `b + 1`,
+//! * one entry per key: key offset, key length, first value and value count as
+//!   `u64`,
+//! * the bytes of all keys,
+//! * the bytes of all values.
+//!
+//! A key belongs to bucket `hash & (bucket count - 1)`, where `hash` is the
+//! 64 bit FNV-1a hash of the key's bytes.
+//!
+//! # Examples
+//!
+//! ```
+//! use multimap::{ArchivedMultiMap, MultiMap};
+//!
+//! let mut map = MultiMap::new();
+//! map.insert("urls", 1u32);
+//! map.insert("urls", 2);
+//! map.insert("id", 42);
+//!
+//! let bytes = map.to_archive();
+//! let archived = ArchivedMultiMap::<u32>::from_bytes(&bytes).unwrap();
+//! assert_eq!(archived.len(), 2);
+//! assert_eq!(archived.get("id"), Some(42));
+//! assert_eq!(archived.get_slice("urls").unwrap().to_vec(), vec![1, 2]);
+//! ```
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, BufWriter, Write};
+use std::marker::PhantomData;
+
+use FrozenMultiMap;
+use MultiMap;
+
+const MAGIC: &[u8; 8] = b"MULTIMAP";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 48;
+const ENTRY_LEN: usize = 32;
+
+/// A value type with a fixed size encoding, which can be stored in an archive.
+pub trait ArchiveValue: Sized {
+    /// The number of bytes of every encoded value.
+    const SIZE: usize;
+
+    /// Appends the encoding of the value, which is exactly `SIZE` bytes long.
+    fn write_bytes(&self, out: &mut Vec<u8>);
+
+    /// Decodes a value from exactly `SIZE` bytes.
+    fn read_bytes(bytes: &[u8]) -> Self;
+}
+
+macro_rules! archive_value_impl {
+    ($($t:ty),*) => {
+        $(
+            impl ArchiveValue for $t {
+                const SIZE: usize = std::mem::size_of::<$t>();
+
+                fn write_bytes(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_bytes(bytes: &[u8]) -> $t {
+                    let mut buf = [0; std::mem::size_of::<$t>()];
+                    buf.copy_from_slice(bytes);
+                    <$t>::from_le_bytes(buf)
+                }
+            }
+        )*
+    };
+}
+
+archive_value_impl!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+/// The error returned when bytes are not a valid archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArchiveError {
+    /// The bytes do not start with the archive magic.
+    BadMagic,
+
+    /// The archive was written in an unsupported format version.
+    UnsupportedVersion(u32),
+
+    /// The archive stores values of a different size than the requested value type.
+    ValueSizeMismatch {
+        /// The size of the requested value type.
+        expected: usize,
+        /// The size of the values in the archive.
+        found: usize,
+    },
+
+    /// The length of the bytes does not match the sizes in the header.
+    LengthMismatch {
+        /// The length computed from the header.
+        expected: usize,
+        /// The actual length of the bytes.
+        found: usize,
+    },
+
+    /// The bucket or entry tables are inconsistent.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArchiveError::BadMagic => f.write_str("not a multimap archive"),
+            ArchiveError::UnsupportedVersion(version) => {
+                write!(f, "unsupported archive version {}", version)
+            }
+            ArchiveError::ValueSizeMismatch { expected, found } => write!(
+                f,
+                "archive stores values of {} bytes, expected {} bytes",
+                found, expected
+            ),
+            ArchiveError::LengthMismatch { expected, found } => write!(
+                f,
+                "archive is {} bytes long, expected {} bytes",
+                found, expected
+            ),
+            ArchiveError::Corrupt(reason) => write!(f, "corrupt archive: {}", reason),
+        }
+    }
+}
+
+impl Error for ArchiveError {}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn read_u64(bytes: &[u8], pos: usize) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&bytes[pos..pos + 8]);
+    u64::from_le_bytes(buf)
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> u32 {
+    let mut buf = [0; 4];
+    buf.copy_from_slice(&bytes[pos..pos + 4]);
+    u32::from_le_bytes(buf)
+}
+
+fn to_usize(n: u64) -> Result<usize, ArchiveError> {
+    usize::try_from(n).map_err(|_| ArchiveError::Corrupt("size does not fit in memory"))
+}
+
+fn checked_len(parts: &[(usize, usize)]) -> Result<usize, ArchiveError> {
+    parts.iter().try_fold(HEADER_LEN, |total, &(count, size)| {
+        count
+            .checked_mul(size)
+            .and_then(|len| total.checked_add(len))
+            .ok_or(ArchiveError::Corrupt("size does not fit in memory"))
+    })
+}
+
+/// Writes the archive for the given keys and values.
+fn write_archive<'a, K, V, I, W>(entries: I, writer: W) -> io::Result<()>
+where
+    K: AsRef<[u8]> + 'a,
+    V: ArchiveValue + 'a,
+    I: Iterator<Item = (&'a K, &'a [V])>,
+    W: Write,
+{
+    let mut entries: Vec<_> = entries
+        .map(|(k, values)| (fnv1a(k.as_ref()), k.as_ref(), values))
+        .collect();
+    let bucket_count = entries.len().max(1).next_power_of_two();
+    let mask = bucket_count as u64 - 1;
+    entries.sort_by_key(|&(hash, _, _)| hash & mask);
+
+    let value_count: usize = entries.iter().map(|(_, _, values)| values.len()).sum();
+    let keys_len: usize = entries.iter().map(|(_, k, _)| k.len()).sum();
+
+    // The sections are streamed to the writer, so only the entry index is
+    // held in memory, never a copy of the keys and values.
+    let mut out = BufWriter::new(writer);
+    out.write_all(MAGIC)?;
+    out.write_all(&VERSION.to_le_bytes())?;
+    out.write_all(&(V::SIZE as u32).to_le_bytes())?;
+    for n in &[entries.len(), value_count, bucket_count, keys_len] {
+        out.write_all(&(*n as u64).to_le_bytes())?;
+    }
+
+    let mut entry = 0;
+    for bucket in 0..=bucket_count as u64 {
+        while entry < entries.len() && entries[entry].0 & mask < bucket {
+            entry += 1;
+        }
+        out.write_all(&(entry as u64).to_le_bytes())?;
+    }
+
+    let (mut key_offset, mut value_offset) = (0, 0);
+    for (_, k, values) in &entries {
+        for n in &[key_offset, k.len(), value_offset, values.len()] {
+            out.write_all(&(*n as u64).to_le_bytes())?;
+        }
+        key_offset += k.len();
+        value_offset += values.len();
+    }
+
+    for (_, k, _) in &entries {
+        out.write_all(k)?;
+    }
+    let mut scratch = Vec::with_capacity(V::SIZE);
+    for (_, _, values) in &entries {
+        for v in values.iter() {
+            scratch.clear();
+            v.write_bytes(&mut scratch);
+            out.write_all(&scratch)?;
+        }
+    }
+
+    out.flush()
+}
+
+impl<K, V, S> MultiMap<K, V, S>
+where
+    K: Eq + Hash + AsRef<[u8]>,
+    V: ArchiveValue,
+    S: BuildHasher,
+{
+    /// Writes the map in the archive format, which can be read in place with
+    /// `ArchivedMultiMap::from_bytes`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert("key", 42u64);
+    ///
+    /// let mut bytes = Vec::new();
+    /// map.write_archive(&mut bytes).unwrap();
+    /// assert_eq!(bytes, map.to_archive());
+    /// ```
+    pub fn write_archive<W: Write>(&self, writer: W) -> io::Result<()> {
+        write_archive(self.iter_all(), writer)
+    }
+
+    /// Returns the map in the archive format, which can be read in place with
+    /// `ArchivedMultiMap::from_bytes`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::{ArchivedMultiMap, MultiMap};
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert("key", 42u64);
+    ///
+    /// let bytes = map.to_archive();
+    /// let archived = ArchivedMultiMap::<u64>::from_bytes(&bytes).unwrap();
+    /// assert_eq!(archived.get("key"), Some(42));
+    /// ```
+    pub fn to_archive(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_archive(&mut bytes)
+            .expect("writing to a Vec cannot fail");
+        bytes
+    }
+}
+
+impl<K, V, S> FrozenMultiMap<K, V, S>
+where
+    K: Eq + Hash + AsRef<[u8]>,
+    V: ArchiveValue,
+    S: BuildHasher,
+{
+    /// Writes the map in the archive format, which can be read in place with
+    /// `ArchivedMultiMap::from_bytes`.
+    pub fn write_archive<W: Write>(&self, writer: W) -> io::Result<()> {
+        write_archive(self.iter_all(), writer)
+    }
+
+    /// Returns the map in the archive format, which can be read in place with
+    /// `ArchivedMultiMap::from_bytes`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::{ArchivedMultiMap, MultiMap};
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(b"key".to_vec(), 42i16);
+    /// map.insert(b"key".to_vec(), -1);
+    ///
+    /// let bytes = map.freeze().to_archive();
+    /// let archived = ArchivedMultiMap::<i16>::from_bytes(&bytes).unwrap();
+    /// assert_eq!(archived.get_slice(b"key").unwrap().to_vec(), vec![42, -1]);
+    /// ```
+    pub fn to_archive(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_archive(&mut bytes)
+            .expect("writing to a Vec cannot fail");
+        bytes
+    }
+}
+
+/// A MultiMap archive which is accessed in place.
+///
+/// The keys are byte strings and the values are decoded from the archive's
+/// bytes whenever they are accessed.
+pub struct ArchivedMultiMap<'a, V> {
+    buckets: &'a [u8],
+    entries: &'a [u8],
+    keys: &'a [u8],
+    values: &'a [u8],
+    marker: PhantomData<fn() -> V>,
+}
+
+impl<'a, V> Clone for ArchivedMultiMap<'a, V> {
+    fn clone(&self) -> ArchivedMultiMap<'a, V> {
+        *self
+    }
+}
+
+impl<'a, V> Copy for ArchivedMultiMap<'a, V> {}
+
+impl<'a, V: ArchiveValue> ArchivedMultiMap<'a, V> {
+    /// Opens the archive stored in the bytes, after validating that the bytes
+    /// are a well formed archive with values of type `V`.
+    ///
+    /// The validation reads all bucket and entry tables and hashes every key
+    /// once, but does not allocate or decode any values. Bytes from untrusted
+    /// sources are safe to open: malformed archives are rejected with an error,
+    /// and accessing a validated archive never panics.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::{ArchiveError, ArchivedMultiMap};
+    ///
+    /// let result = ArchivedMultiMap::<u8>::from_bytes(b"not an archive");
+    /// assert_eq!(result.err(), Some(ArchiveError::BadMagic));
+    /// ```
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<ArchivedMultiMap<'a, V>, ArchiveError> {
+        if bytes.len() < HEADER_LEN || &bytes[..8] != MAGIC {
+            return Err(ArchiveError::BadMagic);
+        }
+        let version = read_u32(bytes, 8);
+        if version != VERSION {
+            return Err(ArchiveError::UnsupportedVersion(version));
+        }
+        let value_size = read_u32(bytes, 12) as usize;
+        if value_size != V::SIZE {
+            return Err(ArchiveError::ValueSizeMismatch {
+                expected: V::SIZE,
+                found: value_size,
+            });
+        }
+
+        let key_count = to_usize(read_u64(bytes, 16))?;
+        let value_count = to_usize(read_u64(bytes, 24))?;
+        let bucket_count = to_usize(read_u64(bytes, 32))?;
+        let keys_len = to_usize(read_u64(bytes, 40))?;
+        if !bucket_count.is_power_of_two() {
+            return Err(ArchiveError::Corrupt("bucket count is not a power of two"));
+        }
+
+        let sections = [
+            (bucket_count + 1, 8),
+            (key_count, ENTRY_LEN),
+            (keys_len, 1),
+            (value_count, value_size),
+        ];
+        let expected = checked_len(&sections)?;
+        if bytes.len() != expected {
+            return Err(ArchiveError::LengthMismatch {
+                expected,
+                found: bytes.len(),
+            });
+        }
+
+        let (buckets, rest) = bytes[HEADER_LEN..].split_at((bucket_count + 1) * 8);
+        let (entries, rest) = rest.split_at(key_count * ENTRY_LEN);
+        let (keys, values) = rest.split_at(keys_len);
+        let archive = ArchivedMultiMap {
+            buckets,
+            entries,
+            keys,
+            values,
+            marker: PhantomData,
+        };
+        archive.validate(key_count, value_count)?;
+        Ok(archive)
+    }
+
+    fn bucket_count(&self) -> usize {
+        self.buckets.len() / 8 - 1
+    }
+
+    fn validate(&self, key_count: usize, value_count: usize) -> Result<(), ArchiveError> {
+        let bucket_count = self.bucket_count();
+        if read_u64(self.buckets, 0) != 0
+            || read_u64(self.buckets, bucket_count * 8) != key_count as u64
+        {
+            return Err(ArchiveError::Corrupt(
+                "bucket offsets do not cover all entries",
+            ));
+        }
+
+        let mask = bucket_count as u64 - 1;
+        let mut start = 0;
+        for bucket in 0..bucket_count {
+            let end = read_u64(self.buckets, (bucket + 1) * 8);
+            if end < start || end > key_count as u64 {
+                return Err(ArchiveError::Corrupt("bucket offsets are not increasing"));
+            }
+            for entry in start as usize..end as usize {
+                let pos = entry * ENTRY_LEN;
+                let key_start = read_u64(self.entries, pos);
+                let key_len = read_u64(self.entries, pos + 8);
+                let value_start = read_u64(self.entries, pos + 16);
+                let value_len = read_u64(self.entries, pos + 24);
+
+                let key_end = key_start
+                    .checked_add(key_len)
+                    .filter(|&end| end <= self.keys.len() as u64)
+                    .ok_or(ArchiveError::Corrupt("key out of bounds"))?;
+                value_start
+                    .checked_add(value_len)
+                    .filter(|&end| end <= value_count as u64)
+                    .ok_or(ArchiveError::Corrupt("values out of bounds"))?;
+
+                let key = &self.keys[key_start as usize..key_end as usize];
+                if fnv1a(key) & mask != bucket as u64 {
+                    return Err(ArchiveError::Corrupt("key stored in the wrong bucket"));
+                }
+            }
+            start = end;
+        }
+        Ok(())
+    }
+
+    fn entry(&self, entry: usize) -> (&'a [u8], ArchivedSlice<'a, V>) {
+        let pos = entry * ENTRY_LEN;
+        let key_start = read_u64(self.entries, pos) as usize;
+        let key_len = read_u64(self.entries, pos + 8) as usize;
+        let value_start = read_u64(self.entries, pos + 16) as usize * V::SIZE;
+        let value_len = read_u64(self.entries, pos + 24) as usize * V::SIZE;
+
+        let key = &self.keys[key_start..key_start + key_len];
+        let values = ArchivedSlice {
+            bytes: &self.values[value_start..value_start + value_len],
+            marker: PhantomData,
+        };
+        (key, values)
+    }
+
+    /// Returns the number of keys in the archive.
+    pub fn len(&self) -> usize {
+        self.entries.len() / ENTRY_LEN
+    }
+
+    /// Returns true if the archive contains no keys.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the total number of values in the archive.
+    pub fn values_len(&self) -> usize {
+        self.values.len().checked_div(V::SIZE).unwrap_or(0)
+    }
+
+    /// Returns true if the archive contains a value for the specified key.
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get_slice(k).is_some()
+    }
+
+    /// Returns the first value corresponding to the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::{ArchivedMultiMap, MultiMap};
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert("key", 42u8);
+    /// map.insert("key", 43);
+    ///
+    /// let bytes = map.to_archive();
+    /// let archived = ArchivedMultiMap::<u8>::from_bytes(&bytes).unwrap();
+    /// assert_eq!(archived.get("key"), Some(42));
+    /// assert_eq!(archived.get("other"), None);
+    /// ```
+    pub fn get<Q>(&self, k: &Q) -> Option<V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get_slice(k)?.get(0)
+    }
+
+    /// Returns the values corresponding to the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::{ArchivedMultiMap, MultiMap};
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert("key", 42u8);
+    /// map.insert("key", 43);
+    ///
+    /// let bytes = map.to_archive();
+    /// let archived = ArchivedMultiMap::<u8>::from_bytes(&bytes).unwrap();
+    /// let values = archived.get_slice("key").unwrap();
+    /// assert_eq!(values.len(), 2);
+    /// assert_eq!(values.iter().sum::<u8>(), 85);
+    /// ```
+    pub fn get_slice<Q>(&self, k: &Q) -> Option<ArchivedSlice<'a, V>>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let k = k.as_ref();
+        let bucket = (fnv1a(k) & (self.bucket_count() as u64 - 1)) as usize;
+        let start = read_u64(self.buckets, bucket * 8) as usize;
+        let end = read_u64(self.buckets, (bucket + 1) * 8) as usize;
+        (start..end)
+            .map(|entry| self.entry(entry))
+            .find(|(key, _)| *key == k)
+            .map(|(_, values)| values)
+    }
+
+    /// An iterator visiting all keys in arbitrary order.
+    pub fn keys(&self) -> impl Iterator<Item = &'a [u8]> + 'a
+    where
+        V: 'a,
+    {
+        self.iter_all().map(|(k, _)| k)
+    }
+
+    /// An iterator visiting all keys and their values in arbitrary order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::{ArchivedMultiMap, MultiMap};
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert("a", 1u32);
+    /// map.insert("a", 2);
+    /// map.insert("b", 3);
+    ///
+    /// let bytes = map.to_archive();
+    /// let archived = ArchivedMultiMap::<u32>::from_bytes(&bytes).unwrap();
+    /// let mut pairs: Vec<_> = archived
+    ///     .iter_all()
+    ///     .map(|(k, values)| (k, values.to_vec()))
+    ///     .collect();
+    /// pairs.sort();
+    /// assert_eq!(pairs, [(&b"a"[..], vec![1, 2]), (&b"b"[..], vec![3])]);
+    /// ```
+    pub fn iter_all(&self) -> impl Iterator<Item = (&'a [u8], ArchivedSlice<'a, V>)> + 'a
+    where
+        V: 'a,
+    {
+        let archive = *self;
+        (0..self.len()).map(move |entry| archive.entry(entry))
+    }
+}
+
+/// The values of one key in an ArchivedMultiMap, decoded on access.
+pub struct ArchivedSlice<'a, V> {
+    bytes: &'a [u8],
+    marker: PhantomData<fn() -> V>,
+}
+
+impl<'a, V> Clone for ArchivedSlice<'a, V> {
+    fn clone(&self) -> ArchivedSlice<'a, V> {
+        *self
+    }
+}
+
+impl<'a, V> Copy for ArchivedSlice<'a, V> {}
+
+impl<'a, V: ArchiveValue> ArchivedSlice<'a, V> {
+    /// Returns the number of values.
+    pub fn len(&self) -> usize {
+        self.bytes.len().checked_div(V::SIZE).unwrap_or(0)
+    }
+
+    /// Returns true if there are no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the value at the given index, or `None` if out of bounds.
+    pub fn get(&self, index: usize) -> Option<V> {
+        if index < self.len() {
+            let start = index * V::SIZE;
+            Some(V::read_bytes(&self.bytes[start..start + V::SIZE]))
+        } else {
+            None
+        }
+    }
+
+    /// An iterator decoding all values in order.
+    pub fn iter(&self) -> impl Iterator<Item = V> + 'a
+    where
+        V: 'a,
+    {
+        let slice = *self;
+        (0..self.len()).map(move |i| {
+            let start = i * V::SIZE;
+            V::read_bytes(&slice.bytes[start..start + V::SIZE])
+        })
+    }
+
+    /// Decodes all values into a vector.
+    pub fn to_vec(&self) -> Vec<V> {
+        self.iter().collect()
+    }
+}
+
+impl<'a, V> fmt::Debug for ArchivedSlice<'a, V>
+where
+    V: ArchiveValue + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let mut map = MultiMap::new();
+        for i in 0..1000u32 {
+            map.insert(format!("key{}", i % 97), i);
+        }
+        map.insert_many("empty".to_string(), vec![]);
+        map.to_archive()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut map = MultiMap::new();
+        for i in 0..1000u32 {
+            map.insert(format!("key{}", i % 97), i);
+        }
+        map.insert_many("empty".to_string(), vec![]);
+
+        let bytes = map.to_archive();
+        let archived = ArchivedMultiMap::<u32>::from_bytes(&bytes).unwrap();
+        assert_eq!(archived.len(), 98);
+        assert_eq!(archived.values_len(), 1000);
+        assert!(archived.get_slice("empty").unwrap().is_empty());
+        assert!(archived.get_slice("missing").is_none());
+        for (k, values) in map.iter_all() {
+            assert_eq!(archived.get_slice(k).unwrap().to_vec(), values);
+        }
+        assert_eq!(archived.keys().count(), 98);
+    }
+
+    /// Accepts at most `limit` bytes and records the largest single write.
+    struct LimitedWriter {
+        written: usize,
+        limit: usize,
+        largest_write: usize,
+    }
+
+    impl Write for LimitedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.written + buf.len() > self.limit {
+                return Err(io::Error::new(io::ErrorKind::WriteZero, "full"));
+            }
+            self.written += buf.len();
+            self.largest_write = self.largest_write.max(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn streams_to_writer() {
+        let mut map = MultiMap::new();
+        for i in 0..100_000u64 {
+            map.insert(format!("key{}", i % 1000), i);
+        }
+        let len = map.to_archive().len();
+
+        let mut writer = LimitedWriter {
+            written: 0,
+            limit: usize::MAX,
+            largest_write: 0,
+        };
+        map.write_archive(&mut writer).unwrap();
+        assert_eq!(writer.written, len);
+        assert!(writer.largest_write < len / 10);
+
+        let mut writer = LimitedWriter {
+            written: 0,
+            limit: len - 1,
+            largest_write: 0,
+        };
+        assert!(map.write_archive(&mut writer).is_err());
+    }
+
+    #[test]
+    fn empty() {
+        let map: MultiMap<String, u64> = MultiMap::new();
+        let bytes = map.to_archive();
+        let archived = ArchivedMultiMap::<u64>::from_bytes(&bytes).unwrap();
+        assert!(archived.is_empty());
+        assert!(archived.get("key").is_none());
+    }
+
+    #[test]
+    fn rejects_wrong_value_type() {
+        let bytes = sample();
+        assert_eq!(
+            ArchivedMultiMap::<u64>::from_bytes(&bytes).err(),
+            Some(ArchiveError::ValueSizeMismatch {
+                expected: 8,
+                found: 4
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_truncated() {
+        let bytes = sample();
+        for len in 0..bytes.len() {
+            assert!(ArchivedMultiMap::<u32>::from_bytes(&bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_corruption_without_panicking() {
+        let bytes = sample();
+        let values_start = bytes.len() - 1000 * 4;
+        // Flip every bit of the header, tables and keys one at a time. Every
+        // archive which still opens must be safe to read completely.
+        for pos in 0..values_start {
+            for bit in 0..8 {
+                let mut corrupt = bytes.clone();
+                corrupt[pos] ^= 1 << bit;
+                if let Ok(archived) = ArchivedMultiMap::<u32>::from_bytes(&corrupt) {
+                    for (k, values) in archived.iter_all() {
+                        values.to_vec();
+                        archived.get_slice(k);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,484 @@
+// Copyright (c) 2016 multimap developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! A MultiMap with limits on the number of keys and values.
+
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp;
+use core::fmt::{self, Debug};
+use core::hash::{BuildHasher, Hash};
+#[cfg(feature = "std")]
+use std::collections::hash_map::RandomState;
+
+use MultiMap;
+
+/// The limits of a BoundedMultiMap. Every limit is unset by default.
+///
+/// # Examples
+///
+/// ```
+/// use multimap::MultiMapLimits;
+///
+/// let limits = MultiMapLimits::new().max_keys(100).max_values_per_key(8);
+/// assert_eq!(limits.get_max_keys(), Some(100));
+/// assert_eq!(limits.get_max_values(), None);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MultiMapLimits {
+    max_keys: Option<usize>,
+    max_values_per_key: Option<usize>,
+    max_values: Option<usize>,
+}
+
+impl MultiMapLimits {
+    /// Creates limits with every limit unset.
+    pub fn new() -> MultiMapLimits {
+        MultiMapLimits::default()
+    }
+
+    /// Sets the maximum number of keys.
+    pub fn max_keys(mut self, max_keys: usize) -> MultiMapLimits {
+        self.max_keys = Some(max_keys);
+        self
+    }
+
+    /// Sets the maximum number of values of each key.
+    pub fn max_values_per_key(mut self, max_values_per_key: usize) -> MultiMapLimits {
+        self.max_values_per_key = Some(max_values_per_key);
+        self
+    }
+
+    /// Sets the maximum total number of values.
+    pub fn max_values(mut self, max_values: usize) -> MultiMapLimits {
+        self.max_values = Some(max_values);
+        self
+    }
+
+    /// Returns the maximum number of keys, if set.
+    pub fn get_max_keys(&self) -> Option<usize> {
+        self.max_keys
+    }
+
+    /// Returns the maximum number of values of each key, if set.
+    pub fn get_max_values_per_key(&self) -> Option<usize> {
+        self.max_values_per_key
+    }
+
+    /// Returns the maximum total number of values, if set.
+    pub fn get_max_values(&self) -> Option<usize> {
+        self.max_values
+    }
+}
+
+/// The error returned when an insert into a BoundedMultiMap would exceed one
+/// of its limits. It carries back the key and the rejected values, which are
+/// a single value for `try_insert` and a `Vec` for `try_insert_many`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BoundedError<K, T> {
+    /// The key is not in the map and the map holds the maximum number of
+    /// keys.
+    KeyLimit {
+        /// The rejected key.
+        key: K,
+        /// The rejected values.
+        rejected: T,
+    },
+
+    /// The key would have more than the maximum number of values per key.
+    PerKeyLimit {
+        /// The rejected key.
+        key: K,
+        /// The rejected values.
+        rejected: T,
+    },
+
+    /// The map would hold more than the maximum total number of values.
+    TotalLimit {
+        /// The rejected key.
+        key: K,
+        /// The rejected values.
+        rejected: T,
+    },
+}
+
+impl<K, T> BoundedError<K, T> {
+    /// Returns the rejected key and values.
+    pub fn into_parts(self) -> (K, T) {
+        match self {
+            BoundedError::KeyLimit { key, rejected }
+            | BoundedError::PerKeyLimit { key, rejected }
+            | BoundedError::TotalLimit { key, rejected } => (key, rejected),
+        }
+    }
+}
+
+impl<K, T> fmt::Display for BoundedError<K, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            BoundedError::KeyLimit { .. } => "maximum number of keys reached",
+            BoundedError::PerKeyLimit { .. } => "maximum number of values per key reached",
+            BoundedError::TotalLimit { .. } => "maximum total number of values reached",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: Debug, T: Debug> std::error::Error for BoundedError<K, T> {}
+
+/// The limit an insert would exceed.
+enum Limit {
+    Key,
+    PerKey,
+    Total,
+}
+
+impl Limit {
+    fn error<K, T>(self, key: K, rejected: T) -> BoundedError<K, T> {
+        match self {
+            Limit::Key => BoundedError::KeyLimit { key, rejected },
+            Limit::PerKey => BoundedError::PerKeyLimit { key, rejected },
+            Limit::Total => BoundedError::TotalLimit { key, rejected },
+        }
+    }
+}
+
+/// A map which allows storing multiple values per key, with limits on the
+/// number of keys, the number of values of each key and the total number of
+/// values. Inserts which would exceed a limit fail and hand back the rejected
+/// key and values.
+///
+/// # Examples
+///
+/// ```
+/// use multimap::{BoundedError, BoundedMultiMap, MultiMapLimits};
+///
+/// let mut map = BoundedMultiMap::new(MultiMapLimits::new().max_keys(1).max_values_per_key(2));
+/// map.try_insert("a", 1).unwrap();
+/// map.try_insert("a", 2).unwrap();
+/// assert_eq!(
+///     map.try_insert("a", 3),
+///     Err(BoundedError::PerKeyLimit { key: "a", rejected: 3 })
+/// );
+/// assert_eq!(
+///     map.try_insert("b", 4),
+///     Err(BoundedError::KeyLimit { key: "b", rejected: 4 })
+/// );
+/// assert_eq!(map.get_slice("a"), Some(&[1, 2][..]));
+/// ```
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct BoundedMultiMap<K, V, S = RandomState> {
+    map: MultiMap<K, V, S>,
+    limits: MultiMapLimits,
+    values: usize,
+}
+
+/// A map which allows storing multiple values per key, with limits on the
+/// number of keys and values.
+#[cfg(not(feature = "std"))]
+#[derive(Clone)]
+pub struct BoundedMultiMap<K, V, S> {
+    map: MultiMap<K, V, S>,
+    limits: MultiMapLimits,
+    values: usize,
+}
+
+#[cfg(feature = "std")]
+impl<K, V> BoundedMultiMap<K, V>
+where
+    K: Eq + Hash,
+{
+    /// Creates an empty map with the given limits.
+    pub fn new(limits: MultiMapLimits) -> BoundedMultiMap<K, V> {
+        BoundedMultiMap::with_hasher(limits, RandomState::new())
+    }
+}
+
+impl<K, V, S> BoundedMultiMap<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Creates an empty map with the given limits, which will use the given
+    /// hash builder to hash keys.
+    pub fn with_hasher(limits: MultiMapLimits, hash_builder: S) -> BoundedMultiMap<K, V, S> {
+        BoundedMultiMap {
+            map: MultiMap::with_hasher(hash_builder),
+            limits,
+            values: 0,
+        }
+    }
+
+    /// Returns the limits of the map.
+    pub fn limits(&self) -> &MultiMapLimits {
+        &self.limits
+    }
+
+    /// Returns the limit which inserting `n` values for the key would exceed.
+    fn check(&self, k: &K, n: usize) -> Option<Limit> {
+        let len = match self.map.get_slice(k) {
+            Some(values) => values.len(),
+            None if self.remaining_keys() == Some(0) => return Some(Limit::Key),
+            None => 0,
+        };
+        if self
+            .limits
+            .max_values_per_key
+            .is_some_and(|max| len + n > max)
+        {
+            return Some(Limit::PerKey);
+        }
+        if self
+            .remaining_values()
+            .is_some_and(|remaining| n > remaining)
+        {
+            return Some(Limit::Total);
+        }
+        None
+    }
+
+    /// Inserts a key-value pair into the map, or returns an error holding the
+    /// key and value if this would exceed a limit. The key limit is checked
+    /// first, then the per-key limit, then the total limit.
+    pub fn try_insert(&mut self, k: K, v: V) -> Result<(), BoundedError<K, V>> {
+        if let Some(limit) = self.check(&k, 1) {
+            return Err(limit.error(k, v));
+        }
+        self.map.insert(k, v);
+        self.values += 1;
+        Ok(())
+    }
+
+    /// Inserts a key with multiple values into the map, or returns an error
+    /// holding the key and all values if this would exceed a limit. Either
+    /// all values are inserted or none are.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::{BoundedError, BoundedMultiMap, MultiMapLimits};
+    ///
+    /// let mut map = BoundedMultiMap::new(MultiMapLimits::new().max_values(3));
+    /// map.try_insert_many("a", vec![1, 2]).unwrap();
+    /// assert_eq!(
+    ///     map.try_insert_many("b", vec![3, 4]),
+    ///     Err(BoundedError::TotalLimit { key: "b", rejected: vec![3, 4] })
+    /// );
+    /// assert_eq!(map.total_values(), 2);
+    /// ```
+    pub fn try_insert_many<I>(&mut self, k: K, v: I) -> Result<(), BoundedError<K, Vec<V>>>
+    where
+        I: IntoIterator<Item = V>,
+    {
+        let v: Vec<V> = v.into_iter().collect();
+        if let Some(limit) = self.check(&k, v.len()) {
+            return Err(limit.error(k, v));
+        }
+        self.values += v.len();
+        self.map.insert_many(k, v);
+        Ok(())
+    }
+
+    /// Returns the number of keys which can still be inserted, or `None` if
+    /// the number of keys is not limited.
+    pub fn remaining_keys(&self) -> Option<usize> {
+        self.limits
+            .max_keys
+            .map(|max| max.saturating_sub(self.map.len()))
+    }
+
+    /// Returns the number of values which can still be inserted in total, or
+    /// `None` if the total number of values is not limited.
+    pub fn remaining_values(&self) -> Option<usize> {
+        self.limits
+            .max_values
+            .map(|max| max.saturating_sub(self.values))
+    }
+
+    /// Returns the number of values which can still be inserted for the key,
+    /// taking every limit into account, or `None` if nothing limits them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::{BoundedMultiMap, MultiMapLimits};
+    ///
+    /// let limits = MultiMapLimits::new().max_keys(1).max_values_per_key(4).max_values(3);
+    /// let mut map = BoundedMultiMap::new(limits);
+    /// map.try_insert("a", 1).unwrap();
+    /// assert_eq!(map.remaining_values_for("a"), Some(2));
+    /// assert_eq!(map.remaining_values_for("b"), Some(0));
+    /// ```
+    pub fn remaining_values_for<Q>(&self, k: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let len = match self.map.get_slice(k) {
+            Some(values) => values.len(),
+            None if self.remaining_keys() == Some(0) => return Some(0),
+            None => 0,
+        };
+        let per_key = self
+            .limits
+            .max_values_per_key
+            .map(|max| max.saturating_sub(len));
+        match (per_key, self.remaining_values()) {
+            (Some(a), Some(b)) => Some(cmp::min(a, b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Returns the number of keys in the map.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns true if the map contains no keys.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns the total number of values in the map.
+    pub fn total_values(&self) -> usize {
+        self.values
+    }
+
+    /// Returns true if the map contains the key.
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.map.contains_key(k)
+    }
+
+    /// Returns a reference to the first value of the key.
+    pub fn get<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.map.get(k)
+    }
+
+    /// Returns the slice of values of the key.
+    pub fn get_slice<Q>(&self, k: &Q) -> Option<&[V]>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.map.get_slice(k)
+    }
+
+    /// Removes a key from the map, returning its values if the key was in the
+    /// map. This frees room for a key and for its values.
+    pub fn remove<Q>(&mut self, k: &Q) -> Option<impl Iterator<Item = V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let values = self.map.inner.remove(k)?;
+        self.values -= values.len();
+        Some(values.into_iter())
+    }
+
+    /// Removes all keys and values.
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.values = 0;
+    }
+
+    /// An iterator visiting all keys in arbitrary order, with the slice of
+    /// values of each key.
+    pub fn iter_all(&self) -> impl Iterator<Item = (&K, &[V])> {
+        self.map.iter_all()
+    }
+
+    /// Returns the underlying MultiMap.
+    pub fn as_multimap(&self) -> &MultiMap<K, V, S> {
+        &self.map
+    }
+
+    /// Converts the map into the underlying MultiMap.
+    pub fn into_multimap(self) -> MultiMap<K, V, S> {
+        self.map
+    }
+}
+
+impl<K, V, S> Debug for BoundedMultiMap<K, V, S>
+where
+    K: Eq + Hash + Debug,
+    V: Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.map.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::*;
+
+    #[test]
+    fn limits_in_order() {
+        let limits = MultiMapLimits::new()
+            .max_keys(2)
+            .max_values_per_key(2)
+            .max_values(3);
+        let mut map = BoundedMultiMap::with_hasher(limits, TestState::default());
+        map.try_insert(1, 'a').unwrap();
+        map.try_insert(1, 'b').unwrap();
+        map.try_insert(2, 'c').unwrap();
+        assert_eq!(map.remaining_keys(), Some(0));
+        assert_eq!(map.remaining_values(), Some(0));
+
+        assert_eq!(
+            map.try_insert(3, 'd'),
+            Err(BoundedError::KeyLimit {
+                key: 3,
+                rejected: 'd'
+            })
+        );
+        assert_eq!(
+            map.try_insert(1, 'd'),
+            Err(BoundedError::PerKeyLimit {
+                key: 1,
+                rejected: 'd'
+            })
+        );
+        let err = map.try_insert(2, 'd').unwrap_err();
+        assert_eq!(err.to_string(), "maximum total number of values reached");
+        assert_eq!(err.into_parts(), (2, 'd'));
+
+        assert_eq!(map.remove(&1).unwrap().count(), 2);
+        assert_eq!(map.total_values(), 1);
+        assert_eq!(map.remaining_values_for(&2), Some(1));
+        assert_eq!(map.remaining_values_for(&3), Some(2));
+        map.try_insert_many(3, vec!['e', 'f']).unwrap();
+        assert_eq!(map.total_values(), 3);
+    }
+
+    #[test]
+    fn unlimited() {
+        let mut map = BoundedMultiMap::with_hasher(MultiMapLimits::new(), TestState::default());
+        for i in 0..100 {
+            map.try_insert(i % 7, i).unwrap();
+        }
+        assert_eq!(map.remaining_keys(), None);
+        assert_eq!(map.remaining_values_for(&0), None);
+        assert_eq!(map.total_values(), 100);
+
+        map.clear();
+        assert!(map.is_empty());
+        assert_eq!(map.total_values(), 0);
+    }
+}
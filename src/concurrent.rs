@@ -0,0 +1,482 @@
+// Copyright (c) 2016 multimap developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! A sharded MultiMap which can be shared between threads.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::ops::Deref;
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use smallvec::{smallvec, SmallVec};
+
+use hash_map::{self, HashMap};
+use MultiMap;
+
+type Shard<K, V, S, const N: usize> = HashMap<K, SmallVec<[V; N]>, S>;
+
+/// A MultiMap which can be read and written from multiple threads at once.
+///
+/// The keys are distributed over a number of shards by the map's hash builder,
+/// and every shard is guarded by its own lock. Threads working on keys in
+/// different shards never wait for each other.
+pub struct ConcurrentMultiMap<K, V, S = RandomState, const N: usize = 1> {
+    shards: Box<[RwLock<Shard<K, V, S, N>>]>,
+    shift: u32,
+    hash_builder: S,
+}
+
+/// A read guard on the values of a single key in a ConcurrentMultiMap.
+///
+/// The shard holding the key stays locked for reading as long as the guard lives.
+pub struct SliceGuard<'a, K: 'a, V: 'a, S: 'a, Q: ?Sized + 'a, const N: usize> {
+    shard: RwLockReadGuard<'a, Shard<K, V, S, N>>,
+    key: &'a Q,
+}
+
+impl<'a, K, V, S, Q, const N: usize> Deref for SliceGuard<'a, K, V, S, Q, N>
+where
+    K: Eq + Hash + Borrow<Q>,
+    Q: ?Sized + Eq + Hash,
+    S: BuildHasher,
+{
+    type Target = [V];
+
+    fn deref(&self) -> &[V] {
+        &self.shard[self.key]
+    }
+}
+
+fn default_shard_amount() -> usize {
+    std::thread::available_parallelism().map_or(1, usize::from) * 4
+}
+
+impl<K, V> ConcurrentMultiMap<K, V>
+where
+    K: Eq + Hash,
+{
+    /// Creates an empty ConcurrentMultiMap with a shard amount based on the
+    /// available parallelism.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::ConcurrentMultiMap;
+    ///
+    /// let map: ConcurrentMultiMap<&str, isize> = ConcurrentMultiMap::new();
+    /// ```
+    pub fn new() -> ConcurrentMultiMap<K, V> {
+        ConcurrentMultiMap::with_hasher(RandomState::new())
+    }
+
+    /// Creates an empty ConcurrentMultiMap with at least the given amount of
+    /// shards. The amount is rounded up to a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::ConcurrentMultiMap;
+    ///
+    /// let map: ConcurrentMultiMap<&str, isize> = ConcurrentMultiMap::with_shard_amount(32);
+    /// ```
+    pub fn with_shard_amount(shard_amount: usize) -> ConcurrentMultiMap<K, V> {
+        ConcurrentMultiMap::with_shard_amount_and_hasher(shard_amount, RandomState::new())
+    }
+}
+
+impl<K, V, S, const N: usize> ConcurrentMultiMap<K, V, S, N>
+where
+    K: Eq + Hash,
+    S: BuildHasher + Clone,
+{
+    /// Creates an empty ConcurrentMultiMap which will use the given hash builder
+    /// to hash keys, both for picking a shard and inside the shards.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::ConcurrentMultiMap;
+    /// use std::collections::hash_map::RandomState;
+    ///
+    /// let s = RandomState::new();
+    /// let map: ConcurrentMultiMap<&str, isize> = ConcurrentMultiMap::with_hasher(s);
+    /// ```
+    pub fn with_hasher(hash_builder: S) -> ConcurrentMultiMap<K, V, S, N> {
+        ConcurrentMultiMap::with_shard_amount_and_hasher(default_shard_amount(), hash_builder)
+    }
+
+    /// Creates an empty ConcurrentMultiMap with at least the given amount of
+    /// shards and the given hash builder. The amount is rounded up to a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::ConcurrentMultiMap;
+    /// use std::collections::hash_map::RandomState;
+    ///
+    /// let s = RandomState::new();
+    /// let map: ConcurrentMultiMap<&str, isize> =
+    ///     ConcurrentMultiMap::with_shard_amount_and_hasher(8, s);
+    /// ```
+    pub fn with_shard_amount_and_hasher(
+        shard_amount: usize,
+        hash_builder: S,
+    ) -> ConcurrentMultiMap<K, V, S, N> {
+        let shard_amount = shard_amount.max(1).next_power_of_two();
+        let shards = (0..shard_amount)
+            .map(|_| RwLock::new(HashMap::with_hasher(hash_builder.clone())))
+            .collect();
+
+        ConcurrentMultiMap {
+            shards,
+            shift: u64::BITS - shard_amount.trailing_zeros(),
+            hash_builder,
+        }
+    }
+
+    /// Returns the index of the shard for the given key. The topmost bits of the
+    /// hash are skipped, since the shard's own table uses them as well.
+    fn shard_index<Q>(&self, k: &Q) -> usize
+    where
+        Q: ?Sized + Hash,
+    {
+        let hash = self.hash_builder.hash_one(k);
+        (hash << 7).checked_shr(self.shift).unwrap_or(0) as usize
+    }
+
+    fn read_shard<Q>(&self, k: &Q) -> RwLockReadGuard<'_, Shard<K, V, S, N>>
+    where
+        Q: ?Sized + Hash,
+    {
+        self.shards[self.shard_index(k)]
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write_shard<Q>(&self, k: &Q) -> RwLockWriteGuard<'_, Shard<K, V, S, N>>
+    where
+        Q: ?Sized + Hash,
+    {
+        self.shards[self.shard_index(k)]
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Inserts a key-value pair into the multimap. If the key does exist in
+    /// the map then the value is pushed to that key's vector. If the key doesn't
+    /// exist in the map a new vector with the given value is inserted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::ConcurrentMultiMap;
+    ///
+    /// let map = ConcurrentMultiMap::new();
+    /// map.insert("key", 42);
+    /// ```
+    pub fn insert(&self, k: K, v: V) {
+        let mut shard = self.write_shard(&k);
+        match shard.entry(k) {
+            hash_map::Entry::Occupied(mut entry) => {
+                entry.get_mut().push(v);
+            }
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(smallvec![v]);
+            }
+        }
+    }
+
+    /// Inserts multiple key-value pairs into the multimap. If the key does exist in
+    /// the map then the values are extended into that key's vector. If the key
+    /// doesn't exist in the map a new vector collected from the given values is inserted.
+    ///
+    /// The values are inserted while holding the shard's lock once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::ConcurrentMultiMap;
+    ///
+    /// let map = ConcurrentMultiMap::new();
+    /// map.insert_many("key", vec![42, 43]);
+    /// ```
+    pub fn insert_many<I: IntoIterator<Item = V>>(&self, k: K, v: I) {
+        let mut shard = self.write_shard(&k);
+        match shard.entry(k) {
+            hash_map::Entry::Occupied(mut entry) => {
+                entry.get_mut().extend(v);
+            }
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(v.into_iter().collect::<_>());
+            }
+        }
+    }
+
+    /// Removes a key from the map, returning the vector of values at
+    /// the key if the key was previously in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::ConcurrentMultiMap;
+    ///
+    /// let map = ConcurrentMultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// assert_eq!(map.remove(&1).map(|i| i.collect::<_>()), Some(vec![42, 1337]));
+    /// assert!(map.remove(&1).is_none());
+    /// ```
+    pub fn remove<Q>(&self, k: &Q) -> Option<impl Iterator<Item = V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.write_shard(k).remove(k).map(|i| i.into_iter())
+    }
+
+    /// Returns true if the map contains a value for the specified key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::ConcurrentMultiMap;
+    ///
+    /// let map = ConcurrentMultiMap::new();
+    /// map.insert(1, 42);
+    /// assert!(map.contains_key(&1));
+    /// assert!(!map.contains_key(&2));
+    /// ```
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.read_shard(k).contains_key(k)
+    }
+
+    /// Returns a copy of the values corresponding to the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::ConcurrentMultiMap;
+    ///
+    /// let map = ConcurrentMultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// assert_eq!(map.get_cloned(&1), Some(vec![42, 1337]));
+    /// ```
+    pub fn get_cloned<Q>(&self, k: &Q) -> Option<Vec<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+        V: Clone,
+    {
+        self.read_shard(k).get(k).map(|v| v.to_vec())
+    }
+
+    /// Returns a guard which dereferences to the values corresponding to the key.
+    ///
+    /// The shard holding the key is locked for reading until the guard is dropped,
+    /// so writes to any key in that shard block in the meantime. Holding the guard
+    /// while writing to the same map from the same thread may deadlock.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::ConcurrentMultiMap;
+    ///
+    /// let map = ConcurrentMultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// assert_eq!(&*map.get_slice(&1).unwrap(), &[42, 1337][..]);
+    /// ```
+    pub fn get_slice<'a, Q>(&'a self, k: &'a Q) -> Option<SliceGuard<'a, K, V, S, Q, N>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let shard = self.read_shard(k);
+        if shard.contains_key(k) {
+            Some(SliceGuard { shard, key: k })
+        } else {
+            None
+        }
+    }
+
+    /// Retains only the elements specified by the predicate.
+    ///
+    /// In other words, remove all pairs `(k, v)` such that `f(&k,&v)` returns `false`.
+    /// The shards are processed one after another, so concurrent writers only wait
+    /// for the shard which is currently being filtered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::ConcurrentMultiMap;
+    ///
+    /// let map = ConcurrentMultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 99);
+    /// map.insert(2, 42);
+    /// map.retain(|&k, &v| k == 1 && v == 42);
+    /// assert_eq!(map.get_cloned(&1), Some(vec![42]));
+    /// assert!(!map.contains_key(&2));
+    /// ```
+    pub fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        for shard in self.shards.iter() {
+            let mut shard = shard.write().unwrap_or_else(PoisonError::into_inner);
+            for (k, v) in shard.iter_mut() {
+                v.retain(|iv| f(k, iv));
+            }
+            shard.retain(|_, v| !v.is_empty());
+        }
+    }
+
+    /// Returns the number of keys in the map.
+    ///
+    /// The shards are counted one after another, so the result may be outdated
+    /// when other threads are writing to the map.
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.read().unwrap_or_else(PoisonError::into_inner).len())
+            .sum()
+    }
+
+    /// Returns true if the map contains no keys.
+    ///
+    /// The shards are checked one after another, so the result may be outdated
+    /// when other threads are writing to the map.
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| {
+            shard
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_empty()
+        })
+    }
+
+    /// Copies the contents of the map into a plain MultiMap.
+    ///
+    /// Every shard is locked for reading while it is copied, so the values of
+    /// each key are consistent. The shards are copied one after another though,
+    /// so writes to different shards which happen during the snapshot may or may
+    /// not be part of it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::ConcurrentMultiMap;
+    ///
+    /// let map = ConcurrentMultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// map.insert(2, 2332);
+    ///
+    /// let snapshot = map.snapshot();
+    /// assert_eq!(snapshot.len(), 2);
+    /// assert_eq!(snapshot.get_slice(&1), Some(&[42, 1337][..]));
+    /// ```
+    pub fn snapshot(&self) -> MultiMap<K, V, S, N>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let mut inner = HashMap::with_hasher(self.hash_builder.clone());
+        for shard in self.shards.iter() {
+            let shard = shard.read().unwrap_or_else(PoisonError::into_inner);
+            inner.extend(shard.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        MultiMap { inner }
+    }
+}
+
+impl<K, V, S, const N: usize> Default for ConcurrentMultiMap<K, V, S, N>
+where
+    K: Eq + Hash,
+    S: BuildHasher + Clone + Default,
+{
+    fn default() -> ConcurrentMultiMap<K, V, S, N> {
+        ConcurrentMultiMap::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S, const N: usize> From<MultiMap<K, V, S, N>> for ConcurrentMultiMap<K, V, S, N>
+where
+    K: Eq + Hash,
+    S: BuildHasher + Clone,
+{
+    fn from(map: MultiMap<K, V, S, N>) -> ConcurrentMultiMap<K, V, S, N> {
+        let concurrent = ConcurrentMultiMap::with_hasher(map.inner.hasher().clone());
+        for (k, v) in map.inner {
+            concurrent.write_shard(&k).insert(k, v);
+        }
+        concurrent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn single_shard() {
+        let map = ConcurrentMultiMap::with_shard_amount(1);
+        map.insert(1, 42);
+        map.insert(2, 1337);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get_cloned(&2), Some(vec![1337]));
+    }
+
+    #[test]
+    fn concurrent_writers() {
+        let map = Arc::new(ConcurrentMultiMap::with_shard_amount(8));
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let map = Arc::clone(&map);
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        map.insert(i % 100, t);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let snapshot = map.snapshot();
+        assert_eq!(snapshot.len(), 100);
+        assert!(snapshot.iter_all().all(|(_, values)| values.len() == 80));
+    }
+
+    #[test]
+    fn get_slice_missing() {
+        let map: ConcurrentMultiMap<usize, usize> = ConcurrentMultiMap::new();
+        assert!(map.get_slice(&1).is_none());
+        map.insert(1, 42);
+        assert_eq!(&*map.get_slice(&1).unwrap(), &[42][..]);
+    }
+
+    #[test]
+    fn from_multimap() {
+        let mut map = MultiMap::new();
+        map.insert(1, 42);
+        map.insert(1, 1337);
+        let concurrent = ConcurrentMultiMap::from(map.clone());
+        assert_eq!(concurrent.snapshot(), map);
+    }
+}
@@ -0,0 +1,460 @@
+// Copyright (c) 2016 multimap developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! CSV and TSV import and export for MultiMap
+//!
+//! Two layouts are supported:
+//!
+//! * In the long layout every row holds one key and one value, so a key with
+//!   several values spans several rows.
+//! * In the wide layout every row holds a key followed by all of its values,
+//!   so rows can have different lengths.
+//!
+//! Keys and values are parsed with `FromStr` when reading and written with
+//! `Display`.
+
+extern crate csv;
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::hash::{BuildHasher, Hash};
+use std::io;
+use std::iter;
+use std::str::FromStr;
+
+use self::csv::{QuoteStyle, ReaderBuilder, StringRecord, WriterBuilder};
+
+use MultiMap;
+
+/// How keys and values are arranged in rows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsvLayout {
+    /// One row per key-value pair: `key,value`.
+    Long,
+    /// One row per key, holding the key followed by all of its values:
+    /// `key,value1,value2`.
+    Wide,
+}
+
+/// Options for reading and writing CSV and TSV files.
+///
+/// # Examples
+///
+/// ```
+/// use multimap::csv::{CsvLayout, CsvOptions};
+///
+/// let options = CsvOptions::tsv().layout(CsvLayout::Wide).has_headers(true);
+/// ```
+#[derive(Clone, Debug)]
+pub struct CsvOptions {
+    layout: CsvLayout,
+    delimiter: u8,
+    quote: u8,
+    quoting: bool,
+    has_headers: bool,
+}
+
+impl CsvOptions {
+    /// Options for comma separated files in the long layout, with `"` quoting
+    /// and without a header row.
+    pub fn csv() -> CsvOptions {
+        CsvOptions {
+            layout: CsvLayout::Long,
+            delimiter: b',',
+            quote: b'"',
+            quoting: true,
+            has_headers: false,
+        }
+    }
+
+    /// Options for tab separated files in the long layout, without quoting and
+    /// without a header row.
+    pub fn tsv() -> CsvOptions {
+        CsvOptions {
+            delimiter: b'\t',
+            quoting: false,
+            ..CsvOptions::csv()
+        }
+    }
+
+    /// Sets the layout of the rows.
+    pub fn layout(mut self, layout: CsvLayout) -> CsvOptions {
+        self.layout = layout;
+        self
+    }
+
+    /// Sets the field delimiter.
+    pub fn delimiter(mut self, delimiter: u8) -> CsvOptions {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Sets the quote character.
+    pub fn quote(mut self, quote: u8) -> CsvOptions {
+        self.quote = quote;
+        self
+    }
+
+    /// Enables or disables quoting. When enabled, quoted fields are unquoted
+    /// when reading, and fields are quoted when needed when writing. When
+    /// disabled, the quote character has no special meaning.
+    pub fn quoting(mut self, quoting: bool) -> CsvOptions {
+        self.quoting = quoting;
+        self
+    }
+
+    /// Sets whether the first row is a header row. It is skipped when
+    /// reading, and written as `key,value` in the long layout or
+    /// `key,values` in the wide layout when writing.
+    pub fn has_headers(mut self, has_headers: bool) -> CsvOptions {
+        self.has_headers = has_headers;
+        self
+    }
+}
+
+impl Default for CsvOptions {
+    fn default() -> CsvOptions {
+        CsvOptions::csv()
+    }
+}
+
+/// The error returned when reading or writing CSV fails.
+#[derive(Debug)]
+pub enum CsvError {
+    /// The input could not be read or the output could not be written, or the
+    /// input is not valid CSV.
+    Csv(csv::Error),
+
+    /// A row in the long layout does not have exactly two fields.
+    FieldCount {
+        /// The line on which the row starts.
+        line: u64,
+        /// The number of fields in the row.
+        found: usize,
+    },
+
+    /// A field could not be parsed into a key or value.
+    Parse {
+        /// The line on which the row starts.
+        line: u64,
+        /// The index of the field in the row, where the key is field 0.
+        field: usize,
+        /// The error returned by `FromStr`.
+        message: String,
+    },
+
+    /// A key or value contains the delimiter or a line break and cannot be
+    /// written because quoting is disabled.
+    Unquotable {
+        /// The key or value.
+        field: String,
+    },
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CsvError::Csv(e) => e.fmt(f),
+            CsvError::FieldCount { line, found } => write!(
+                f,
+                "line {}: expected 2 fields, found {} fields",
+                line, found
+            ),
+            CsvError::Parse {
+                line,
+                field,
+                message,
+            } => write!(f, "line {}, field {}: {}", line, field, message),
+            CsvError::Unquotable { field } => write!(
+                f,
+                "field {:?} contains the delimiter or a line break but quoting is disabled",
+                field
+            ),
+        }
+    }
+}
+
+impl Error for CsvError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CsvError::Csv(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<csv::Error> for CsvError {
+    fn from(e: csv::Error) -> CsvError {
+        CsvError::Csv(e)
+    }
+}
+
+impl From<io::Error> for CsvError {
+    fn from(e: io::Error) -> CsvError {
+        CsvError::Csv(e.into())
+    }
+}
+
+fn parse<T>(field: &str, line: u64, index: usize) -> Result<T, CsvError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    field.parse().map_err(|e: T::Err| CsvError::Parse {
+        line,
+        field: index,
+        message: e.to_string(),
+    })
+}
+
+impl<K, V, S> MultiMap<K, V, S>
+where
+    K: Eq + Hash + FromStr,
+    K::Err: Display,
+    V: FromStr,
+    V::Err: Display,
+    S: BuildHasher + Default,
+{
+    /// Reads a MultiMap from CSV or TSV data. The values of each key are
+    /// inserted in the order in which they appear.
+    ///
+    /// In the wide layout, a row holding only a key inserts the key without
+    /// values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::csv::CsvOptions;
+    /// use multimap::MultiMap;
+    ///
+    /// let data = "fruit,apple\nveg,leek\nfruit,pear\n";
+    /// let map: MultiMap<String, String> =
+    ///     MultiMap::read_csv(data.as_bytes(), &CsvOptions::csv()).unwrap();
+    /// assert_eq!(map.get_slice("fruit").unwrap(), ["apple", "pear"]);
+    /// ```
+    pub fn read_csv<R: io::Read>(reader: R, options: &CsvOptions) -> Result<Self, CsvError> {
+        let mut reader = ReaderBuilder::new()
+            .delimiter(options.delimiter)
+            .quote(options.quote)
+            .quoting(options.quoting)
+            .has_headers(options.has_headers)
+            .flexible(true)
+            .from_reader(reader);
+
+        let mut map = MultiMap::default();
+        let mut record = StringRecord::new();
+        while reader.read_record(&mut record)? {
+            let line = record.position().map_or(0, |p| p.line());
+            let key = parse(&record[0], line, 0)?;
+            match options.layout {
+                CsvLayout::Long => {
+                    if record.len() != 2 {
+                        return Err(CsvError::FieldCount {
+                            line,
+                            found: record.len(),
+                        });
+                    }
+                    map.insert(key, parse(&record[1], line, 1)?);
+                }
+                CsvLayout::Wide => {
+                    let values = record
+                        .iter()
+                        .enumerate()
+                        .skip(1)
+                        .map(|(i, field)| parse(field, line, i))
+                        .collect::<Result<Vec<V>, _>>()?;
+                    map.insert_many(key, values);
+                }
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+impl<K, V, S> MultiMap<K, V, S>
+where
+    K: Eq + Hash + Display,
+    V: Display,
+    S: BuildHasher,
+{
+    /// Writes the map as CSV or TSV data. Keys are written in arbitrary order,
+    /// and the values of each key in their order in the map.
+    ///
+    /// In the long layout, keys without values are not written.
+    ///
+    /// If `has_headers` is set, a header row is written first, so the output
+    /// can be read back with the same options.
+    ///
+    /// When quoting is disabled, as for TSV by default, a key or value which
+    /// contains the delimiter or a line break could not be read back, and
+    /// `CsvError::Unquotable` is returned instead. Rows written before the
+    /// failing field are left in the writer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::csv::{CsvLayout, CsvOptions};
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert("fruit", "apple");
+    /// map.insert("fruit", "pear, conference");
+    ///
+    /// let mut out = Vec::new();
+    /// map.write_csv(&mut out, &CsvOptions::csv().layout(CsvLayout::Wide))
+    ///     .unwrap();
+    /// assert_eq!(out, b"fruit,apple,\"pear, conference\"\n");
+    /// ```
+    pub fn write_csv<W: io::Write>(&self, writer: W, options: &CsvOptions) -> Result<(), CsvError> {
+        let mut writer = WriterBuilder::new()
+            .delimiter(options.delimiter)
+            .quote(options.quote)
+            .quote_style(if options.quoting {
+                QuoteStyle::Necessary
+            } else {
+                QuoteStyle::Never
+            })
+            .has_headers(false)
+            .flexible(true)
+            .from_writer(writer);
+
+        let field = |v: &dyn Display| -> Result<String, CsvError> {
+            let field = v.to_string();
+            if !options.quoting
+                && field
+                    .bytes()
+                    .any(|b| b == options.delimiter || b == b'\n' || b == b'\r')
+            {
+                return Err(CsvError::Unquotable { field });
+            }
+            Ok(field)
+        };
+
+        if options.has_headers {
+            writer.write_record(match options.layout {
+                CsvLayout::Long => ["key", "value"],
+                CsvLayout::Wide => ["key", "values"],
+            })?;
+        }
+
+        for (key, values) in self.iter_all() {
+            let key = field(key)?;
+            match options.layout {
+                CsvLayout::Long => {
+                    for value in values {
+                        writer.write_record([&key, &field(value)?])?;
+                    }
+                }
+                CsvLayout::Wide => {
+                    let values = values
+                        .iter()
+                        .map(|v| field(v))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    writer.write_record(iter::once(key).chain(values))?;
+                }
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_long() {
+        let data = "key,value\na,1\nb,2\na,\"3\"\n";
+        let options = CsvOptions::csv().has_headers(true);
+        let map: MultiMap<String, u32> = MultiMap::read_csv(data.as_bytes(), &options).unwrap();
+        assert_eq!(map.get_slice("a"), Some(&[1, 3][..]));
+        assert_eq!(map.get_slice("b"), Some(&[2][..]));
+    }
+
+    #[test]
+    fn read_wide_tsv() {
+        let data = "a\t1\t2\nb\na\t3\n";
+        let options = CsvOptions::tsv().layout(CsvLayout::Wide);
+        let map: MultiMap<char, u8> = MultiMap::read_csv(data.as_bytes(), &options).unwrap();
+        assert_eq!(map.get_slice(&'a'), Some(&[1, 2, 3][..]));
+        assert_eq!(map.get_slice(&'b'), Some(&[][..]));
+    }
+
+    #[test]
+    fn read_errors() {
+        let options = CsvOptions::csv();
+        let result = MultiMap::<u8, u8>::read_csv(&b"1,2\n3,x\n"[..], &options);
+        match result {
+            Err(CsvError::Parse { line, field, .. }) => assert_eq!((line, field), (2, 1)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let result = MultiMap::<u8, u8>::read_csv(&b"1,2,3\n"[..], &options);
+        match result {
+            Err(CsvError::FieldCount { line, found }) => assert_eq!((line, found), (1, 3)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tsv_embedded_delimiter() {
+        let mut map: MultiMap<String, String> = MultiMap::new();
+        map.insert("k\t1".to_string(), "a\tb".to_string());
+        map.insert("k\t1".to_string(), "line\nbreak".to_string());
+
+        let mut out = Vec::new();
+        match map.write_csv(&mut out, &CsvOptions::tsv()) {
+            Err(CsvError::Unquotable { field }) => assert!(field.contains('\t')),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        for &layout in &[CsvLayout::Long, CsvLayout::Wide] {
+            let options = CsvOptions::tsv().layout(layout).quoting(true);
+            let mut out = Vec::new();
+            map.write_csv(&mut out, &options).unwrap();
+            let back: MultiMap<String, String> = MultiMap::read_csv(&out[..], &options).unwrap();
+            assert_eq!(back, map);
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut map: MultiMap<String, String> = MultiMap::new();
+        map.insert("k,1".to_string(), "a \"quoted\" value".to_string());
+        map.insert("k,1".to_string(), "line\nbreak".to_string());
+        map.insert("k2".to_string(), "".to_string());
+
+        for &layout in &[CsvLayout::Long, CsvLayout::Wide] {
+            let options = CsvOptions::csv().layout(layout).delimiter(b';');
+            let mut out = Vec::new();
+            map.write_csv(&mut out, &options).unwrap();
+            let back: MultiMap<String, String> = MultiMap::read_csv(&out[..], &options).unwrap();
+            assert_eq!(back, map);
+        }
+    }
+
+    #[test]
+    fn roundtrip_with_headers() {
+        let mut map: MultiMap<String, u32> = MultiMap::new();
+        map.insert("a".to_string(), 1);
+        map.insert("a".to_string(), 2);
+        map.insert("b".to_string(), 3);
+
+        for &layout in &[CsvLayout::Long, CsvLayout::Wide] {
+            let options = CsvOptions::csv().layout(layout).has_headers(true);
+            let mut out = Vec::new();
+            map.write_csv(&mut out, &options).unwrap();
+            assert!(out.starts_with(b"key,value"));
+            let back: MultiMap<String, u32> = MultiMap::read_csv(&out[..], &options).unwrap();
+            assert_eq!(back, map);
+        }
+    }
+}
@@ -0,0 +1,387 @@
+// Copyright (c) 2016 multimap developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! A read-only MultiMap which stores all values in one buffer.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt::{self, Debug};
+use core::hash::{BuildHasher, Hash};
+use core::ops::{Index, Range};
+#[cfg(feature = "std")]
+use std::collections::hash_map::RandomState;
+
+use hash_map::HashMap;
+use MultiMap;
+
+/// A read-only map which allows storing multiple values per key.
+///
+/// All values are stored in a single contiguous buffer, in which the values of
+/// each key occupy one range. The keys are kept in a hash map from the key to
+/// its range. Compared to a MultiMap, there is no per-key allocation and no
+/// unused inline or spare capacity.
+///
+/// A FrozenMultiMap is created with `MultiMap::freeze` and can be turned back
+/// into a MultiMap with `thaw`.
+///
+/// # Examples
+///
+/// ```
+/// use multimap::MultiMap;
+///
+/// let mut map = MultiMap::new();
+/// map.insert("key1", 42);
+/// map.insert("key1", 1337);
+/// map.insert("key2", 2332);
+///
+/// let frozen = map.freeze();
+/// assert_eq!(frozen.get_slice("key1"), Some(&[42, 1337][..]));
+/// assert_eq!(frozen.len(), 2);
+///
+/// let map = frozen.thaw();
+/// assert_eq!(map.get_slice("key2"), Some(&[2332][..]));
+/// ```
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct FrozenMultiMap<K, V, S = RandomState> {
+    index: HashMap<K, Range<usize>, S>,
+    values: Box<[V]>,
+}
+
+/// A read-only map which allows storing multiple values per key.
+#[cfg(not(feature = "std"))]
+#[derive(Clone)]
+pub struct FrozenMultiMap<K, V, S> {
+    index: HashMap<K, Range<usize>, S>,
+    values: Box<[V]>,
+}
+
+impl<K, V, S> MultiMap<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher + Clone,
+{
+    /// Converts the map into a read-only FrozenMultiMap, which stores all values
+    /// in one contiguous buffer. The values of every key keep their order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// let frozen = map.freeze();
+    /// assert_eq!(frozen.get_slice(&1), Some(&[42, 1337][..]));
+    /// ```
+    pub fn freeze(self) -> FrozenMultiMap<K, V, S> {
+        let total = self.inner.values().map(|v| v.len()).sum();
+        let mut index = HashMap::with_capacity_and_hasher(self.len(), self.inner.hasher().clone());
+        let mut values = Vec::with_capacity(total);
+        for (k, v) in self.inner {
+            let start = values.len();
+            values.extend(v);
+            index.insert(k, start..values.len());
+        }
+
+        FrozenMultiMap {
+            index,
+            values: values.into_boxed_slice(),
+        }
+    }
+}
+
+impl<K, V, S> FrozenMultiMap<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Returns the number of keys in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// map.insert(2, 2332);
+    /// assert_eq!(map.freeze().len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns true if the map contains no keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let map: MultiMap<usize, usize> = MultiMap::new();
+    /// assert!(map.freeze().is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns the total number of values in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// map.insert(2, 2332);
+    /// assert_eq!(map.freeze().values_len(), 3);
+    /// ```
+    pub fn values_len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns true if the map contains a value for the specified key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but Hash and Eq
+    /// on the borrowed form must match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// let frozen = map.freeze();
+    /// assert!(frozen.contains_key(&1));
+    /// assert!(!frozen.contains_key(&2));
+    /// ```
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.index.contains_key(k)
+    }
+
+    /// Returns a reference to the first item in the slice corresponding to
+    /// the key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but Hash and Eq
+    /// on the borrowed form must match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// assert_eq!(map.freeze().get(&1), Some(&42));
+    /// ```
+    pub fn get<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.get_slice(k)?.first()
+    }
+
+    /// Returns a reference to the slice of values corresponding to the key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but Hash and Eq
+    /// on the borrowed form must match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// assert_eq!(map.freeze().get_slice(&1), Some(&[42, 1337][..]));
+    /// ```
+    pub fn get_slice<Q>(&self, k: &Q) -> Option<&[V]>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.index.get(k).map(|range| &self.values[range.clone()])
+    }
+
+    /// An iterator visiting all keys in arbitrary order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// map.insert(2, 2332);
+    ///
+    /// let frozen = map.freeze();
+    /// let mut keys: Vec<_> = frozen.keys().collect();
+    /// keys.sort();
+    /// assert_eq!(keys, [&1, &2]);
+    /// ```
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.index.keys()
+    }
+
+    /// An iterator visiting all key-value pairs in arbitrary order. The iterator returns
+    /// a reference to the key and the corresponding key's slice of values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// map.insert(3, 2332);
+    ///
+    /// let frozen = map.freeze();
+    /// let mut pairs: Vec<_> = frozen.iter_all().collect();
+    /// pairs.sort_by_key(|p| p.0);
+    /// assert_eq!(pairs, [(&1, &[42, 1337][..]), (&3, &[2332][..])]);
+    /// ```
+    pub fn iter_all(&self) -> impl Iterator<Item = (&K, &[V])> {
+        self.index
+            .iter()
+            .map(move |(k, range)| (k, &self.values[range.clone()]))
+    }
+
+    /// Converts the map back into a MultiMap. The values are moved, not cloned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// let mut map = map.freeze().thaw();
+    /// map.insert(1, 1337);
+    /// assert_eq!(map.get_slice(&1), Some(&[42, 1337][..]));
+    /// ```
+    pub fn thaw(self) -> MultiMap<K, V, S>
+    where
+        S: Clone,
+    {
+        let mut map = MultiMap::with_capacity_and_hasher(self.len(), self.index.hasher().clone());
+
+        let mut entries: Vec<_> = self.index.into_iter().collect();
+        entries.sort_unstable_by_key(|(_, range)| range.start);
+
+        let mut values = self.values.into_vec().into_iter();
+        for (k, range) in entries {
+            let v = values.by_ref().take(range.len()).collect();
+            map.inner.insert(k, v);
+        }
+        map
+    }
+}
+
+impl<K, V, S, Q: ?Sized> Index<&Q> for FrozenMultiMap<K, V, S>
+where
+    K: Eq + Hash + Borrow<Q>,
+    Q: Eq + Hash,
+    S: BuildHasher,
+{
+    type Output = V;
+
+    fn index(&self, index: &Q) -> &V {
+        self.get(index).expect("no entry found for key")
+    }
+}
+
+impl<K, V, S> Debug for FrozenMultiMap<K, V, S>
+where
+    K: Eq + Hash + Debug,
+    V: Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter_all()).finish()
+    }
+}
+
+impl<K, V, S> PartialEq for FrozenMultiMap<K, V, S>
+where
+    K: Eq + Hash,
+    V: PartialEq,
+    S: BuildHasher,
+{
+    fn eq(&self, other: &FrozenMultiMap<K, V, S>) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+
+        self.iter_all()
+            .all(|(key, value)| other.get_slice(key) == Some(value))
+    }
+}
+
+impl<K, V, S> Eq for FrozenMultiMap<K, V, S>
+where
+    K: Eq + Hash,
+    V: Eq,
+    S: BuildHasher,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use test_util::*;
+
+    #[test]
+    fn freeze_thaw_roundtrip() {
+        let mut map = TestMap::default();
+        for i in 0..100 {
+            map.insert(i % 7, i);
+        }
+        map.insert_many(7, vec![]);
+
+        let frozen = map.clone().freeze();
+        assert_eq!(frozen.len(), 8);
+        assert_eq!(frozen.values_len(), 100);
+        assert_eq!(frozen.get_slice(&7), Some(&[][..]));
+        for (k, values) in map.iter_all() {
+            assert_eq!(frozen.get_slice(k), Some(values));
+        }
+
+        assert_eq!(frozen.thaw(), map);
+    }
+
+    #[test]
+    fn index() {
+        let mut map = TestMap::default();
+        map.insert(1, 42);
+        let frozen = map.freeze();
+        assert_eq!(frozen[&1], 42);
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_no_entry() {
+        let map: TestMap<usize, usize> = TestMap::default();
+        let frozen = map.freeze();
+        let _ = &frozen[&1];
+    }
+}
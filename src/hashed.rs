@@ -0,0 +1,225 @@
+// Copyright (c) 2016 multimap developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Lookups with precomputed hashes and borrowed-key entries, available with
+//! the hashbrown backend.
+//!
+//! A hash computed by `MultiMap::hash_key` is only valid for maps whose hash
+//! builders produce the same hashes, such as maps created with clones of one
+//! hash builder. Passing a hash that does not match the key gives unspecified
+//! but memory safe results: the key may not be found, or may be inserted
+//! twice.
+
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+
+use hash_map::{self, RawEntryMut};
+use smallvec::{smallvec, SmallVec};
+
+use MultiMap;
+
+impl<K, V, S> MultiMap<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Computes the hash of a key with the map's hash builder, for use with
+    /// `get_slice_hashed` and `insert_hashed`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert("key", 42);
+    /// let hash = map.hash_key("key");
+    /// assert_eq!(map.get_slice_hashed(hash, |k| *k == "key"), Some(&[42][..]));
+    /// ```
+    pub fn hash_key<Q>(&self, k: &Q) -> u64
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash,
+    {
+        self.inner.hasher().hash_one(k)
+    }
+
+    /// Returns the slice of values of the key with the given hash for which
+    /// `is_match` returns true, without hashing the key again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    /// use std::collections::hash_map::RandomState;
+    ///
+    /// let hasher = RandomState::new();
+    /// let mut a = MultiMap::with_hasher(hasher.clone());
+    /// let mut b = MultiMap::with_hasher(hasher);
+    /// a.insert("key".to_string(), 1);
+    /// b.insert("key".to_string(), 2);
+    ///
+    /// let hash = a.hash_key("key");
+    /// assert_eq!(a.get_slice_hashed(hash, |k| k == "key"), Some(&[1][..]));
+    /// assert_eq!(b.get_slice_hashed(hash, |k| k == "key"), Some(&[2][..]));
+    /// ```
+    pub fn get_slice_hashed<F>(&self, hash: u64, is_match: F) -> Option<&[V]>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        self.inner
+            .raw_entry()
+            .from_hash(hash, is_match)
+            .map(|(_, v)| &v[..])
+    }
+
+    /// Inserts a key-value pair into the map using a precomputed hash of the
+    /// key. If the key already exists, the value is appended to its values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// let hash = map.hash_key(&1);
+    /// map.insert_hashed(hash, 1, 42);
+    /// map.insert_hashed(hash, 1, 1337);
+    /// assert_eq!(map.get_slice(&1), Some(&[42, 1337][..]));
+    /// ```
+    pub fn insert_hashed(&mut self, hash: u64, k: K, v: V) {
+        match self.inner.raw_entry_mut().from_key_hashed_nocheck(hash, &k) {
+            RawEntryMut::Occupied(mut entry) => entry.get_mut().push(v),
+            RawEntryMut::Vacant(entry) => {
+                entry.insert_hashed_nocheck(hash, k, smallvec![v]);
+            }
+        }
+    }
+
+    /// Gets the entry of a key given in borrowed form. The key is only
+    /// converted into an owned key when a value is inserted into a vacant
+    /// entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map: MultiMap<String, usize> = MultiMap::new();
+    /// map.entry_ref("key").insert(42);
+    /// map.entry_ref("key").insert(1337);
+    /// assert_eq!(map.entry_ref("key").or_insert(0), &mut 42);
+    /// assert_eq!(map.entry_ref("other").or_insert(7), &mut 7);
+    /// assert_eq!(map.get_slice("key"), Some(&[42, 1337][..]));
+    /// ```
+    pub fn entry_ref<'a, 'b, Q>(&'a mut self, k: &'b Q) -> EntryRef<'a, 'b, K, Q, V, S>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        EntryRef {
+            inner: self.inner.entry_ref(k),
+        }
+    }
+}
+
+/// A view into an entry of a MultiMap, obtained with a borrowed key by
+/// `MultiMap::entry_ref`.
+pub struct EntryRef<'a, 'b, K, Q: ?Sized, V, S> {
+    inner: hash_map::EntryRef<'a, 'b, K, Q, SmallVec<[V; 1]>, S>,
+}
+
+impl<'a, 'b, K, Q, V, S> EntryRef<'a, 'b, K, Q, V, S>
+where
+    K: Hash + Borrow<Q>,
+    Q: ?Sized + Hash,
+    &'b Q: Into<K>,
+    S: BuildHasher,
+{
+    /// Returns the key of the entry.
+    pub fn key(&self) -> &Q {
+        self.inner.key()
+    }
+
+    /// Returns true if the key is in the map.
+    pub fn is_occupied(&self) -> bool {
+        match self.inner {
+            hash_map::EntryRef::Occupied(_) => true,
+            hash_map::EntryRef::Vacant(_) => false,
+        }
+    }
+
+    /// Appends a value to the values of the key, inserting the key if it is
+    /// vacant. Returns the values of the key.
+    pub fn insert(self, value: V) -> &'a mut [V] {
+        match self.inner {
+            hash_map::EntryRef::Occupied(entry) => {
+                let values = entry.into_mut();
+                values.push(value);
+                values
+            }
+            hash_map::EntryRef::Vacant(entry) => entry.insert(smallvec![value]),
+        }
+    }
+
+    /// Ensures the key has values by inserting the default if the entry is
+    /// vacant, and returns a mutable reference to the first value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        let values = self.inner.or_default();
+        if values.is_empty() {
+            values.push(default);
+        }
+        &mut values[0]
+    }
+
+    /// Inserts the key with the given values if the entry is vacant, and
+    /// returns the values of the key.
+    pub fn or_insert_many<I>(self, defaults: I) -> &'a mut [V]
+    where
+        I: IntoIterator<Item = V>,
+    {
+        self.inner.or_insert_with(|| defaults.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_util::*;
+
+    #[test]
+    fn hashed_roundtrip() {
+        let mut map: TestMap<String, u32> = TestMap::default();
+        for i in 0..100 {
+            let key = (i % 10).to_string();
+            let hash = map.hash_key(key.as_str());
+            map.insert_hashed(hash, key, i);
+        }
+
+        assert_eq!(map.len(), 10);
+        let hash = map.hash_key("3");
+        assert_eq!(map.get_slice_hashed(hash, |k| k == "3").unwrap().len(), 10);
+        assert_eq!(map.get_slice_hashed(hash, |k| k == "4"), None);
+        assert_eq!(map.get_slice("3"), map.get_slice_hashed(hash, |k| k == "3"));
+    }
+
+    #[test]
+    fn entry_ref() {
+        let mut map: TestMap<String, u32> = TestMap::default();
+        assert!(!map.entry_ref("a").is_occupied());
+        assert_eq!(map.entry_ref("a").key(), "a");
+        assert_eq!(map.entry_ref("a").or_insert_many(vec![1, 2]), &mut [1, 2]);
+        assert_eq!(map.entry_ref("a").or_insert_many(vec![3]), &mut [1, 2]);
+        assert!(map.entry_ref("a").is_occupied());
+        assert_eq!(map.entry_ref("a").insert(4), &mut [1, 2, 4]);
+        *map.entry_ref("a").or_insert(0) = 9;
+        assert_eq!(map.get_slice("a"), Some(&[9, 2, 4][..]));
+
+        assert_eq!(map.entry_ref("b").or_insert_many(vec![]), &mut []);
+        assert_eq!(map.entry_ref("b").or_insert(5), &mut 5);
+    }
+}
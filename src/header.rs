@@ -0,0 +1,562 @@
+// Copyright (c) 2016 multimap developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! A MultiMap for HTTP headers, with ASCII case-insensitive names.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt::{self, Debug, Display};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::iter::FromIterator;
+
+use MultiMap;
+
+/// A string key which is hashed and compared ignoring ASCII case, while
+/// keeping its original casing.
+///
+/// # Examples
+///
+/// ```
+/// use multimap::{CaseInsensitive, MultiMap};
+///
+/// let mut map = MultiMap::new();
+/// map.insert(CaseInsensitive::from("Content-Type"), "text/html");
+/// assert_eq!(map.get(&CaseInsensitive::from("content-type")), Some(&"text/html"));
+/// assert_eq!(map.keys().next().unwrap().as_str(), "Content-Type");
+/// ```
+#[derive(Clone, Default)]
+pub struct CaseInsensitive(String);
+
+impl CaseInsensitive {
+    /// Creates a new key from a string.
+    pub fn new(s: String) -> CaseInsensitive {
+        CaseInsensitive(s)
+    }
+
+    /// Returns the key with its original casing.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the key as a string with its original casing.
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl From<String> for CaseInsensitive {
+    fn from(s: String) -> CaseInsensitive {
+        CaseInsensitive(s)
+    }
+}
+
+impl<'a> From<&'a str> for CaseInsensitive {
+    fn from(s: &'a str) -> CaseInsensitive {
+        CaseInsensitive(s.to_string())
+    }
+}
+
+impl PartialEq for CaseInsensitive {
+    fn eq(&self, other: &CaseInsensitive) -> bool {
+        self.0.eq_ignore_ascii_case(&other.0)
+    }
+}
+
+impl Eq for CaseInsensitive {}
+
+impl Hash for CaseInsensitive {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_ignore_ascii_case(&self.0, state)
+    }
+}
+
+impl Debug for CaseInsensitive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(&self.0, f)
+    }
+}
+
+impl Display for CaseInsensitive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+fn hash_ignore_ascii_case<H: Hasher>(s: &str, state: &mut H) {
+    for b in s.bytes() {
+        state.write_u8(b.to_ascii_lowercase());
+    }
+    state.write_u8(0xff);
+}
+
+/// Lets a `CaseInsensitive` key be looked up with a `&str` without allocating.
+///
+/// This trait is public only because it appears in a `Borrow` implementation;
+/// it cannot be named outside of this crate.
+pub trait HeaderKey {
+    fn name(&self) -> &str;
+}
+
+impl HeaderKey for CaseInsensitive {
+    fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl HeaderKey for &str {
+    fn name(&self) -> &str {
+        self
+    }
+}
+
+impl<'a> Borrow<dyn HeaderKey + 'a> for CaseInsensitive {
+    fn borrow(&self) -> &(dyn HeaderKey + 'a) {
+        self
+    }
+}
+
+impl<'a> PartialEq for dyn HeaderKey + 'a {
+    fn eq(&self, other: &(dyn HeaderKey + 'a)) -> bool {
+        self.name().eq_ignore_ascii_case(other.name())
+    }
+}
+
+impl<'a> Eq for dyn HeaderKey + 'a {}
+
+impl<'a> Hash for dyn HeaderKey + 'a {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_ignore_ascii_case(self.name(), state)
+    }
+}
+
+/// A map of HTTP header names to their values.
+///
+/// Names are compared ignoring ASCII case, as HTTP requires, but keep the
+/// casing with which they were first added, so headers can be written out the
+/// way they were received. The values of a name keep their order.
+///
+/// # Examples
+///
+/// ```
+/// use multimap::HeaderMultiMap;
+///
+/// let mut headers = HeaderMultiMap::new();
+/// headers.append("Set-Cookie", "a=1");
+/// headers.append("set-cookie", "b=2");
+/// headers.insert("Content-Type", "text/html");
+///
+/// assert_eq!(headers.get_all("SET-COOKIE"), ["a=1", "b=2"]);
+/// assert_eq!(headers.get("content-type"), Some("text/html"));
+///
+/// let mut names: Vec<_> = headers.names().collect();
+/// names.sort();
+/// assert_eq!(names, ["Content-Type", "Set-Cookie"]);
+/// ```
+#[derive(Clone)]
+pub struct HeaderMultiMap<S = RandomState> {
+    inner: MultiMap<CaseInsensitive, String, S>,
+}
+
+impl HeaderMultiMap<RandomState> {
+    /// Creates an empty HeaderMultiMap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::HeaderMultiMap;
+    ///
+    /// let headers = HeaderMultiMap::new();
+    /// assert!(headers.is_empty());
+    /// ```
+    pub fn new() -> HeaderMultiMap<RandomState> {
+        HeaderMultiMap {
+            inner: MultiMap::new(),
+        }
+    }
+}
+
+impl<S> HeaderMultiMap<S>
+where
+    S: BuildHasher,
+{
+    /// Creates an empty HeaderMultiMap which will use the given hash builder
+    /// to hash header names.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::HeaderMultiMap;
+    /// use std::collections::hash_map::RandomState;
+    ///
+    /// let headers = HeaderMultiMap::with_hasher(RandomState::new());
+    /// assert!(headers.is_empty());
+    /// ```
+    pub fn with_hasher(hash_builder: S) -> HeaderMultiMap<S> {
+        HeaderMultiMap {
+            inner: MultiMap::with_hasher(hash_builder),
+        }
+    }
+
+    /// Returns the number of distinct header names in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::HeaderMultiMap;
+    ///
+    /// let mut headers = HeaderMultiMap::new();
+    /// headers.append("Accept", "text/html");
+    /// headers.append("accept", "text/plain");
+    /// assert_eq!(headers.len(), 1);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns true if the map contains no headers.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Adds a value to the header with the given name, keeping any existing
+    /// values. If the name is not yet in the map, it is added with the given
+    /// casing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::HeaderMultiMap;
+    ///
+    /// let mut headers = HeaderMultiMap::new();
+    /// headers.append("Via", "1.1 a");
+    /// headers.append("VIA", "1.1 b");
+    /// assert_eq!(headers.get_all("via"), ["1.1 a", "1.1 b"]);
+    /// ```
+    pub fn append<N, V>(&mut self, name: N, value: V)
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        self.inner
+            .insert(CaseInsensitive(name.into()), value.into());
+    }
+
+    /// Sets the header with the given name to a single value, replacing the
+    /// name's casing and all of its values. Returns the previous values, if
+    /// any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::HeaderMultiMap;
+    ///
+    /// let mut headers = HeaderMultiMap::new();
+    /// headers.append("accept", "text/html");
+    /// headers.append("accept", "text/plain");
+    /// assert_eq!(
+    ///     headers.insert("Accept", "*/*"),
+    ///     Some(vec!["text/html".to_string(), "text/plain".to_string()])
+    /// );
+    /// assert_eq!(headers.get_all("accept"), ["*/*"]);
+    /// assert_eq!(headers.names().next(), Some("Accept"));
+    /// ```
+    pub fn insert<N, V>(&mut self, name: N, value: V) -> Option<Vec<String>>
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        let name = name.into();
+        let previous = self.remove(&name);
+        self.inner.insert(CaseInsensitive(name), value.into());
+        previous
+    }
+
+    /// Returns true if the map contains the header.
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.inner.contains_key(&name as &dyn HeaderKey)
+    }
+
+    /// Returns the first value of the header.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::HeaderMultiMap;
+    ///
+    /// let mut headers = HeaderMultiMap::new();
+    /// headers.append("Host", "example.com");
+    /// assert_eq!(headers.get("host"), Some("example.com"));
+    /// assert_eq!(headers.get("origin"), None);
+    /// ```
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.inner
+            .get(&name as &dyn HeaderKey)
+            .map(|value| value.as_str())
+    }
+
+    /// Returns all values of the header, in order. The slice is empty if the
+    /// header is not in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::HeaderMultiMap;
+    ///
+    /// let mut headers = HeaderMultiMap::new();
+    /// headers.append("Warning", "110");
+    /// headers.append("Warning", "112");
+    /// assert_eq!(headers.get_all("warning"), ["110", "112"]);
+    /// assert!(headers.get_all("age").is_empty());
+    /// ```
+    pub fn get_all(&self, name: &str) -> &[String] {
+        self.inner.get_slice(&name as &dyn HeaderKey).unwrap_or(&[])
+    }
+
+    /// Removes the header from the map, returning its values if it was
+    /// present.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::HeaderMultiMap;
+    ///
+    /// let mut headers = HeaderMultiMap::new();
+    /// headers.append("Cookie", "a=1");
+    /// assert_eq!(headers.remove("COOKIE"), Some(vec!["a=1".to_string()]));
+    /// assert!(headers.is_empty());
+    /// ```
+    pub fn remove(&mut self, name: &str) -> Option<Vec<String>> {
+        self.inner
+            .remove(&name as &dyn HeaderKey)
+            .map(|values| values.collect())
+    }
+
+    /// An iterator visiting all header names, with their original casing, in
+    /// arbitrary order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.inner.keys().map(|name| name.as_str())
+    }
+
+    /// An iterator visiting every name-value pair. Names are visited in
+    /// arbitrary order and the values of each name in order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::HeaderMultiMap;
+    ///
+    /// let mut headers = HeaderMultiMap::new();
+    /// headers.append("Accept", "text/html");
+    /// headers.append("accept", "text/plain");
+    /// let pairs: Vec<_> = headers.iter().collect();
+    /// assert_eq!(pairs, [("Accept", "text/html"), ("Accept", "text/plain")]);
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.inner.iter_all().flat_map(|(name, values)| {
+            values
+                .iter()
+                .map(move |value| (name.as_str(), value.as_str()))
+        })
+    }
+
+    /// Returns a reference to the underlying MultiMap.
+    pub fn as_multimap(&self) -> &MultiMap<CaseInsensitive, String, S> {
+        &self.inner
+    }
+
+    /// Converts the map into the underlying MultiMap.
+    pub fn into_multimap(self) -> MultiMap<CaseInsensitive, String, S> {
+        self.inner
+    }
+}
+
+impl<S> Default for HeaderMultiMap<S>
+where
+    S: BuildHasher + Default,
+{
+    fn default() -> HeaderMultiMap<S> {
+        HeaderMultiMap {
+            inner: MultiMap::default(),
+        }
+    }
+}
+
+impl<S> Debug for HeaderMultiMap<S>
+where
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.inner.iter_all()).finish()
+    }
+}
+
+impl<S> PartialEq for HeaderMultiMap<S>
+where
+    S: BuildHasher,
+{
+    fn eq(&self, other: &HeaderMultiMap<S>) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<S> Eq for HeaderMultiMap<S> where S: BuildHasher {}
+
+impl<S> From<MultiMap<CaseInsensitive, String, S>> for HeaderMultiMap<S> {
+    fn from(inner: MultiMap<CaseInsensitive, String, S>) -> HeaderMultiMap<S> {
+        HeaderMultiMap { inner }
+    }
+}
+
+impl<N, V, S> FromIterator<(N, V)> for HeaderMultiMap<S>
+where
+    N: Into<String>,
+    V: Into<String>,
+    S: BuildHasher + Default,
+{
+    fn from_iter<T: IntoIterator<Item = (N, V)>>(iterable: T) -> HeaderMultiMap<S> {
+        let mut headers = HeaderMultiMap::default();
+        headers.extend(iterable);
+        headers
+    }
+}
+
+impl<N, V, S> Extend<(N, V)> for HeaderMultiMap<S>
+where
+    N: Into<String>,
+    V: Into<String>,
+    S: BuildHasher,
+{
+    fn extend<T: IntoIterator<Item = (N, V)>>(&mut self, iter: T) {
+        for (name, value) in iter {
+            self.append(name, value);
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+mod http_impl {
+    extern crate http;
+
+    use std::convert::TryFrom;
+    use std::hash::BuildHasher;
+
+    use self::http::header::{HeaderName, HeaderValue, ToStrError};
+    use self::http::HeaderMap;
+
+    use super::HeaderMultiMap;
+
+    impl<'a, S> TryFrom<&'a HeaderMap> for HeaderMultiMap<S>
+    where
+        S: BuildHasher + Default,
+    {
+        type Error = ToStrError;
+
+        /// Converts an `http::HeaderMap`, failing if a value is not visible
+        /// ASCII. The `http` crate stores names in lower case.
+        fn try_from(map: &'a HeaderMap) -> Result<HeaderMultiMap<S>, ToStrError> {
+            let mut headers = HeaderMultiMap::default();
+            for (name, value) in map {
+                headers.append(name.as_str(), value.to_str()?);
+            }
+            Ok(headers)
+        }
+    }
+
+    impl<'a, S> TryFrom<&'a HeaderMultiMap<S>> for HeaderMap
+    where
+        S: BuildHasher,
+    {
+        type Error = http::Error;
+
+        /// Converts into an `http::HeaderMap`, failing if a name or value is
+        /// not valid in HTTP.
+        fn try_from(headers: &'a HeaderMultiMap<S>) -> Result<HeaderMap, http::Error> {
+            let mut map = HeaderMap::with_capacity(headers.len());
+            for (name, value) in headers.iter() {
+                let name = HeaderName::from_bytes(name.as_bytes())?;
+                map.append(name, HeaderValue::from_str(value)?);
+            }
+            Ok(map)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn http_roundtrip() {
+            let mut headers: HeaderMultiMap = HeaderMultiMap::new();
+            headers.append("Set-Cookie", "a=1");
+            headers.append("set-cookie", "b=2");
+            headers.append("Host", "example.com");
+
+            let map = HeaderMap::try_from(&headers).unwrap();
+            assert_eq!(map.get_all("set-cookie").iter().count(), 2);
+            assert_eq!(map["host"], "example.com");
+
+            let back: HeaderMultiMap = HeaderMultiMap::try_from(&map).unwrap();
+            assert_eq!(back, headers);
+            assert_eq!(back.get_all("SET-COOKIE"), ["a=1", "b=2"]);
+        }
+
+        #[test]
+        fn http_invalid() {
+            let mut headers: HeaderMultiMap = HeaderMultiMap::new();
+            headers.append("bad name", "x");
+            assert!(HeaderMap::try_from(&headers).is_err());
+
+            let mut map = HeaderMap::new();
+            map.insert("x", HeaderValue::from_bytes(b"\xff").unwrap());
+            assert!(
+                HeaderMultiMap::<::std::collections::hash_map::RandomState>::try_from(&map)
+                    .is_err()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_insensitive_lookup() {
+        let mut headers = HeaderMultiMap::new();
+        headers.append("X-Request-Id", "1");
+        headers.append("x-request-id", "2");
+        headers.append("X-REQUEST-ID", "3");
+
+        assert_eq!(headers.len(), 1);
+        assert!(headers.contains_key("x-Request-ID"));
+        assert_eq!(headers.get_all("x-request-id"), ["1", "2", "3"]);
+        assert_eq!(headers.names().collect::<Vec<_>>(), ["X-Request-Id"]);
+        assert!(!headers.contains_key("x-request"));
+    }
+
+    #[test]
+    fn insert_replaces() {
+        let mut headers = HeaderMultiMap::new();
+        assert_eq!(headers.insert("a", "1"), None);
+        headers.append("A", "2");
+        assert_eq!(
+            headers.insert("A", "3"),
+            Some(vec!["1".to_string(), "2".to_string()])
+        );
+        assert_eq!(headers.iter().collect::<Vec<_>>(), [("A", "3")]);
+    }
+
+    #[test]
+    fn case_insensitive_key() {
+        let a = CaseInsensitive::from("Content-Length");
+        let b = CaseInsensitive::from("content-LENGTH");
+        assert_eq!(a, b);
+        assert_ne!(a, CaseInsensitive::from("Content-Type"));
+        assert_eq!(a.to_string(), "Content-Length");
+    }
+}
@@ -1,4 +1,14 @@
 #![forbid(unsafe_code)]
+// Lints raised by the original API and tests, kept as written.
+#![allow(
+    clippy::get_first,
+    clippy::multiple_bound_locations,
+    clippy::needless_borrow,
+    clippy::needless_lifetimes,
+    clippy::unnecessary_map_or,
+    clippy::useless_vec
+)]
+#![cfg_attr(not(feature = "std"), no_std)]
 // Copyright (c) 2016 multimap developers
 //
 // Licensed under the Apache License, Version 2.0
@@ -65,35 +75,136 @@
 //! assert_eq!(map.get("key1"), Some(&42));
 //! assert_eq!(map.get_slice("key1"), Some(&vec![42, 1337][..]));
 //! ```
-
+//!
+//! # `no_std`
+//!
+//! The crate supports `no_std` targets with `alloc`. Disable the default `std`
+//! feature and enable the `hashbrown` feature, which stores the keys in a
+//! `hashbrown::HashMap` instead of a `std::collections::HashMap`. Without `std`
+//! there is no `RandomState`, so the hasher type parameter has no default and
+//! maps are created with `with_hasher` or `Default`. `ConcurrentMultiMap`,
+//! `HeaderMultiMap`, the archive format and the legacy entry types require
+//! `std`.
+//!
+//! The examples in this documentation use `MultiMap::new` and therefore
+//! require `std`. The unit tests build their maps with a hasher which is
+//! available in both configurations, and are run without `std` with
+//! `cargo test --no-default-features --features hashbrown,serde_impl --lib`.
+
+#[cfg(not(any(feature = "std", feature = "hashbrown")))]
+compile_error!("multimap requires either the `std` or the `hashbrown` feature");
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate core;
+#[cfg(all(test, not(feature = "std")))]
+#[macro_use]
+extern crate std;
+#[cfg(feature = "hashbrown")]
+extern crate hashbrown;
 extern crate smallvec;
 
-use std::borrow::Borrow;
+use alloc::borrow::ToOwned;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt::{self, Debug};
+use core::hash::{BuildHasher, Hash};
+use core::iter::{FromIterator, FusedIterator, IntoIterator, Iterator};
+use core::ops::Index;
+#[cfg(feature = "std")]
 use std::collections::hash_map::RandomState;
-use std::collections::HashMap;
-use std::fmt::{self, Debug};
-use std::hash::{BuildHasher, Hash};
-use std::iter::{FromIterator, IntoIterator, Iterator};
-use std::ops::Index;
+
+#[cfg(feature = "hashbrown")]
+use hashbrown::hash_map;
+#[cfg(not(feature = "hashbrown"))]
+use std::collections::hash_map;
+
+use hash_map::HashMap;
 
 use smallvec::{smallvec, SmallVec};
-pub use std::collections::hash_map::Iter as IterAll;
-pub use std::collections::hash_map::IterMut as IterAllMut;
 
+pub use advisor::{AdvisorGoal, CapacityAdvice, CapacityAdvisor, CapacityEstimate};
+#[cfg(feature = "allocator-api2")]
+pub use allocator::AllocMultiMap;
+#[cfg(feature = "std")]
+pub use archive::{ArchiveError, ArchiveValue, ArchivedMultiMap, ArchivedSlice};
+pub use bounded::{BoundedError, BoundedMultiMap, MultiMapLimits};
+#[cfg(feature = "std")]
+pub use concurrent::{ConcurrentMultiMap, SliceGuard};
+#[cfg(feature = "std")]
 pub use entry::{Entry, OccupiedEntry, VacantEntry};
-
+pub use frozen::FrozenMultiMap;
+#[cfg(feature = "hashbrown")]
+pub use hashed::EntryRef;
+#[cfg(feature = "std")]
+pub use header::{CaseInsensitive, HeaderMultiMap};
+pub use lru::{LruLimit, LruMultiMap};
+pub use persistent::{PersistentIterAll, PersistentMultiMap};
+pub use reserve::{TableReserveError, TryReserveError};
+pub use ring::{EvictionPolicy, RingMultiMap};
+pub use stats::MultiMapStats;
+
+mod advisor;
+#[cfg(feature = "allocator-api2")]
+mod allocator;
+#[cfg(feature = "std")]
+pub mod archive;
+mod bounded;
+#[cfg(feature = "std")]
+mod concurrent;
+#[cfg(feature = "std")]
 mod entry;
+mod frozen;
+#[cfg(feature = "hashbrown")]
+mod hashed;
+#[cfg(feature = "std")]
+mod header;
+mod lru;
+mod persistent;
+mod query;
+mod reserve;
+mod ring;
+mod stats;
+
+#[cfg(feature = "csv")]
+pub mod csv;
+
+#[cfg(feature = "rayon")]
+pub mod rayon;
 
-/*
 #[cfg(feature = "serde_impl")]
 pub mod serde;
- */
 
+/// Types shared by the unit tests, which run with and without `std`.
+#[cfg(test)]
+mod test_util {
+    pub use std::string::{String, ToString};
+    pub use std::vec::Vec;
+
+    use core::hash::BuildHasherDefault;
+    use std::collections::hash_map::DefaultHasher;
+
+    use MultiMap;
+
+    /// A hash builder which, unlike `RandomState`, is available without `std`.
+    pub type TestState = BuildHasherDefault<DefaultHasher>;
+
+    /// A MultiMap which can be created with `Default` in every configuration.
+    pub type TestMap<K, V> = MultiMap<K, V, TestState>;
+}
+
+#[cfg(feature = "std")]
 #[derive(Clone)]
 pub struct MultiMap<K, V, S = RandomState, const N: usize = 1> {
     inner: HashMap<K, smallvec::SmallVec<[V; N]>, S>,
 }
 
+#[cfg(not(feature = "std"))]
+#[derive(Clone)]
+pub struct MultiMap<K, V, S, const N: usize = 1> {
+    inner: HashMap<K, smallvec::SmallVec<[V; N]>, S>,
+}
+
 pub trait MultiMapValue {
     type Item;
     fn as_slice(&mut self) -> &mut [Self::Item];
@@ -117,6 +228,38 @@ impl<V, const N: usize> MultiMapValue for &mut smallvec::SmallVec<[V; N]> {
     }
 }
 
+/// Decides how values are combined when they are moved onto a key that
+/// already exists in the map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Append the moved values after the existing values.
+    Append,
+
+    /// Replace the existing values with the moved values.
+    Replace,
+
+    /// Keep the existing values and drop the moved values.
+    Keep,
+}
+
+/// The outcome of resolving a key that exists in both maps passed to
+/// `MultiMap::merge_with`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MergeResolution<V> {
+    /// Append their values after our values.
+    Append,
+
+    /// Replace our values with their values.
+    Replace,
+
+    /// Keep our values and drop their values.
+    KeepOurs,
+
+    /// Replace both with the given values.
+    Values(Vec<V>),
+}
+
+#[cfg(feature = "std")]
 impl<K, V> MultiMap<K, V>
 where
     K: Eq + Hash,
@@ -132,7 +275,7 @@ where
     /// ```
     pub fn new() -> MultiMap<K, V> {
         MultiMap {
-            inner: HashMap::new(),
+            inner: HashMap::with_hasher(RandomState::new()),
         }
     }
 
@@ -147,7 +290,7 @@ where
     /// ```
     pub fn with_capacity(capacity: usize) -> MultiMap<K, V> {
         MultiMap {
-            inner: HashMap::with_capacity(capacity),
+            inner: HashMap::with_capacity_and_hasher(capacity, RandomState::new()),
         }
     }
 }
@@ -205,10 +348,10 @@ where
     /// ```
     pub fn insert(&mut self, k: K, v: V) {
         match self.inner.entry(k) {
-            std::collections::hash_map::Entry::Occupied(mut entry) => {
+            hash_map::Entry::Occupied(mut entry) => {
                 entry.get_mut().push(v);
             }
-            std::collections::hash_map::Entry::Vacant(entry) => {
+            hash_map::Entry::Vacant(entry) => {
                 entry.insert(smallvec![v]);
             }
         }
@@ -230,10 +373,10 @@ where
     /// ```
     pub fn insert_many<I: IntoIterator<Item = V>>(&mut self, k: K, v: I) {
         match self.inner.entry(k) {
-            std::collections::hash_map::Entry::Occupied(mut entry) => {
+            hash_map::Entry::Occupied(mut entry) => {
                 entry.get_mut().extend(v);
             }
-            std::collections::hash_map::Entry::Vacant(entry) => {
+            hash_map::Entry::Vacant(entry) => {
                 entry.insert(v.into_iter().collect::<_>());
             }
         }
@@ -258,10 +401,10 @@ where
         V: Copy,
     {
         match self.inner.entry(k) {
-            std::collections::hash_map::Entry::Occupied(mut entry) => {
+            hash_map::Entry::Occupied(mut entry) => {
                 entry.get_mut().extend_from_slice(v);
             }
-            std::collections::hash_map::Entry::Vacant(entry) => {
+            hash_map::Entry::Vacant(entry) => {
                 entry.insert(SmallVec::from_slice(v));
             }
         }
@@ -331,6 +474,68 @@ where
         self.inner.remove(k).map(|i| i.into_iter())
     }
 
+    /// Removes a key from the map, returning the stored key and the vector of
+    /// values at the key if the key was previously in the map.
+    ///
+    /// The key may be any borrowed form of the map's key type, but Hash and Eq
+    /// on the borrowed form must match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert("key".to_string(), 42);
+    /// map.insert("key".to_string(), 1337);
+    /// let (key, values) = map.remove_entry("key").unwrap();
+    /// assert_eq!(key, "key");
+    /// assert_eq!(values.collect::<Vec<_>>(), vec![42, 1337]);
+    /// assert!(map.remove_entry("key").is_none());
+    /// ```
+    pub fn remove_entry<Q>(&mut self, k: &Q) -> Option<(K, impl Iterator<Item = V>)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.inner.remove_entry(k).map(|(k, v)| (k, v.into_iter()))
+    }
+
+    /// Removes all the given keys from the map, dropping their values. Returns
+    /// the number of keys that were present in the map.
+    ///
+    /// The keys may be any borrowed form of the map's key type, but Hash and Eq
+    /// on the borrowed form must match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// map.insert(2, 2332);
+    /// map.insert(3, 1991);
+    /// assert_eq!(map.remove_many(&[1, 3, 4]), 2);
+    /// assert_eq!(map.len(), 1);
+    /// assert!(map.contains_key(&2));
+    /// ```
+    pub fn remove_many<'q, Q, I>(&mut self, keys: I) -> usize
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash + 'q,
+        I: IntoIterator<Item = &'q Q>,
+    {
+        let mut removed = 0;
+        for k in keys {
+            if self.inner.remove(k).is_some() {
+                removed += 1;
+            }
+        }
+        removed
+    }
+
     /// Returns a reference to the first item in the vector corresponding to
     /// the key.
     ///
@@ -471,7 +676,9 @@ where
         }
     }
 
-    /// Returns the number of elements the map can hold without reallocating.
+    /// Returns the number of keys the map can hold without reallocating the
+    /// hash table. The capacity of the values of each key is separate; see
+    /// `stats`.
     ///
     /// # Examples
     ///
@@ -713,6 +920,584 @@ where
 
         self.inner.retain(|_, v| !v.is_empty());
     }
+
+    /// Retains only the keys specified by the predicate.
+    ///
+    /// In other words, remove all keys `k` such that `f(&k, &values)` returns `false`.
+    /// Unlike `retain`, the predicate is called once per key with all of the key's values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut m = MultiMap::new();
+    /// m.insert(1, 42);
+    /// m.insert(1, 99);
+    /// m.insert(2, 42);
+    /// m.retain_keys(|_, values| values.len() > 1);
+    /// assert_eq!(1, m.len());
+    /// assert_eq!(Some(&[42, 99][..]), m.get_slice(&1));
+    /// ```
+    pub fn retain_keys<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &[V]) -> bool,
+    {
+        self.inner.retain(|k, v| f(k, &v[..]));
+    }
+
+    /// Sorts the values of every key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut m = MultiMap::new();
+    /// m.insert(1, 99);
+    /// m.insert(1, 42);
+    /// m.insert(2, 7);
+    /// m.insert(2, 3);
+    /// m.sort_values();
+    /// assert_eq!(Some(&[42, 99][..]), m.get_slice(&1));
+    /// assert_eq!(Some(&[3, 7][..]), m.get_slice(&2));
+    /// ```
+    pub fn sort_values(&mut self)
+    where
+        V: Ord,
+    {
+        for v in self.inner.values_mut() {
+            v.sort();
+        }
+    }
+
+    /// Sorts the values of every key with a comparator function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut m = MultiMap::new();
+    /// m.insert(1, 42);
+    /// m.insert(1, 99);
+    /// m.sort_values_by(|a, b| b.cmp(a));
+    /// assert_eq!(Some(&[99, 42][..]), m.get_slice(&1));
+    /// ```
+    pub fn sort_values_by<F>(&mut self, mut compare: F)
+    where
+        F: FnMut(&V, &V) -> core::cmp::Ordering,
+    {
+        for v in self.inner.values_mut() {
+            v.sort_by(&mut compare);
+        }
+    }
+
+    /// Sorts the values of every key with a key extraction function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut m = MultiMap::new();
+    /// m.insert(1, -99);
+    /// m.insert(1, 42);
+    /// m.sort_values_by_key(|v: &i32| v.abs());
+    /// assert_eq!(Some(&[42, -99][..]), m.get_slice(&1));
+    /// ```
+    pub fn sort_values_by_key<B, F>(&mut self, mut f: F)
+    where
+        B: Ord,
+        F: FnMut(&V) -> B,
+    {
+        for v in self.inner.values_mut() {
+            v.sort_by_key(&mut f);
+        }
+    }
+
+    /// Removes consecutive repeated values of every key. If the values are
+    /// sorted, this removes all duplicates.
+    ///
+    /// Vectors which spilled to the heap but fit inline after deduplication
+    /// are moved back to their inline buffer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut m = MultiMap::new();
+    /// m.insert(1, 42);
+    /// m.insert(1, 42);
+    /// m.insert(1, 99);
+    /// m.dedup_values();
+    /// assert_eq!(Some(&[42, 99][..]), m.get_slice(&1));
+    /// ```
+    pub fn dedup_values(&mut self)
+    where
+        V: PartialEq,
+    {
+        for v in self.inner.values_mut() {
+            v.dedup();
+            if v.spilled() {
+                v.shrink_to_fit();
+            }
+        }
+    }
+
+    /// Removes consecutive values of every key that resolve to the same key.
+    ///
+    /// Vectors which spilled to the heap but fit inline after deduplication
+    /// are moved back to their inline buffer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut m = MultiMap::new();
+    /// m.insert(1, 10);
+    /// m.insert(1, 11);
+    /// m.insert(1, 20);
+    /// m.dedup_values_by_key(|v| *v / 10);
+    /// assert_eq!(Some(&[10, 20][..]), m.get_slice(&1));
+    /// ```
+    pub fn dedup_values_by_key<B, F>(&mut self, mut f: F)
+    where
+        B: PartialEq,
+        F: FnMut(&mut V) -> B,
+    {
+        for v in self.inner.values_mut() {
+            v.dedup_by_key(&mut f);
+            if v.spilled() {
+                v.shrink_to_fit();
+            }
+        }
+    }
+
+    /// Sorts and deduplicates the values of every key, so that each key holds
+    /// a sorted list of distinct values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut m = MultiMap::new();
+    /// m.insert(1, 99);
+    /// m.insert(1, 42);
+    /// m.insert(1, 99);
+    /// m.normalize();
+    /// assert_eq!(Some(&[42, 99][..]), m.get_slice(&1));
+    /// ```
+    pub fn normalize(&mut self)
+    where
+        V: Ord,
+    {
+        self.sort_values();
+        self.dedup_values();
+    }
+
+    /// Moves the values of the key `old` to the key `new`, removing `old` from
+    /// the map. If `new` already exists in the map, the policy decides how the
+    /// two vectors are combined. Returns true if `old` was in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::{MergePolicy, MultiMap};
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(2, 1337);
+    /// assert!(map.rename_key(&1, 3, MergePolicy::Append));
+    /// assert!(map.rename_key(&3, 2, MergePolicy::Append));
+    /// assert_eq!(map.get_slice(&2), Some(&[1337, 42][..]));
+    /// assert!(!map.contains_key(&1));
+    /// assert!(!map.contains_key(&3));
+    /// ```
+    pub fn rename_key<Q>(&mut self, old: &Q, new: K, policy: MergePolicy) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let values = match self.inner.remove(old) {
+            Some(values) => values,
+            None => return false,
+        };
+
+        match self.inner.entry(new) {
+            hash_map::Entry::Occupied(mut entry) => match policy {
+                MergePolicy::Append => entry.get_mut().extend(values),
+                MergePolicy::Replace => *entry.get_mut() = values,
+                MergePolicy::Keep => {}
+            },
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(values);
+            }
+        }
+        true
+    }
+
+    /// Moves the values of the key `from` for which the predicate returns true
+    /// to the end of the vector of the key `to`. Nothing is moved if either key
+    /// is not in the map. If all values of `from` are moved, `from` is removed
+    /// from the map. Returns the number of moved values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert_many(1, vec![1, 2, 3, 4]);
+    /// map.insert(2, 0);
+    /// assert_eq!(map.move_values(&1, &2, |v| v % 2 == 0), 2);
+    /// assert_eq!(map.get_slice(&1), Some(&[1, 3][..]));
+    /// assert_eq!(map.get_slice(&2), Some(&[0, 2, 4][..]));
+    /// ```
+    pub fn move_values<Q, F>(&mut self, from: &Q, to: &Q, mut f: F) -> usize
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+        F: FnMut(&V) -> bool,
+    {
+        let (key, values) = match self.inner.remove_entry(from) {
+            Some(entry) => entry,
+            None => return 0,
+        };
+        let dest = match self.inner.get_mut(to) {
+            Some(dest) => dest,
+            None => {
+                self.inner.insert(key, values);
+                return 0;
+            }
+        };
+
+        let before = dest.len();
+        let mut kept = SmallVec::new();
+        for v in values {
+            if f(&v) {
+                dest.push(v);
+            } else {
+                kept.push(v);
+            }
+        }
+        let moved = dest.len() - before;
+
+        if moved == 0 || !kept.is_empty() {
+            self.inner.insert(key, kept);
+        }
+        moved
+    }
+
+    /// Swaps the vectors of the keys `a` and `b`. Returns true if both keys
+    /// were in the map, otherwise the map is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(2, 1337);
+    /// map.insert(2, 2332);
+    /// assert!(map.swap_keys(&1, &2));
+    /// assert_eq!(map.get_slice(&1), Some(&[1337, 2332][..]));
+    /// assert_eq!(map.get_slice(&2), Some(&[42][..]));
+    /// ```
+    pub fn swap_keys<Q>(&mut self, a: &Q, b: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let (key, mut values) = match self.inner.remove_entry(a) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        let swapped = match self.inner.get_mut(b) {
+            Some(other) => {
+                core::mem::swap(other, &mut values);
+                true
+            }
+            None => false,
+        };
+        self.inner.insert(key, values);
+        swapped
+    }
+
+    /// Moves all values of the key `from` to the end of the vector of the key
+    /// `to` and removes `from` from the map. Returns true if both keys were in
+    /// the map, otherwise the map is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(2, 1337);
+    /// assert!(map.append_key(&1, &2));
+    /// assert_eq!(map.get_slice(&2), Some(&[1337, 42][..]));
+    /// assert!(!map.contains_key(&1));
+    /// ```
+    pub fn append_key<Q>(&mut self, from: &Q, to: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let (key, values) = match self.inner.remove_entry(from) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        match self.inner.get_mut(to) {
+            Some(dest) => {
+                dest.extend(values);
+                true
+            }
+            None => {
+                self.inner.insert(key, values);
+                false
+            }
+        }
+    }
+
+    /// Moves all keys and values of `other` into the map. Keys which only exist in
+    /// `other` are inserted as they are. For keys which exist in both maps the
+    /// resolver is called with the key, our values and their values, and decides
+    /// which values the key ends up with.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::{MergeResolution, MultiMap};
+    ///
+    /// let mut ours = MultiMap::new();
+    /// ours.insert("a", 1);
+    /// ours.insert("b", 2);
+    /// ours.insert("c", 3);
+    ///
+    /// let mut theirs = MultiMap::new();
+    /// theirs.insert("a", 10);
+    /// theirs.insert("b", 20);
+    /// theirs.insert("c", 30);
+    /// theirs.insert("d", 40);
+    ///
+    /// ours.merge_with(theirs, |key, ours, theirs| match *key {
+    ///     "a" => MergeResolution::Append,
+    ///     "b" => MergeResolution::KeepOurs,
+    ///     _ => MergeResolution::Values(vec![ours[0] + theirs[0]]),
+    /// });
+    ///
+    /// assert_eq!(ours.get_slice("a"), Some(&[1, 10][..]));
+    /// assert_eq!(ours.get_slice("b"), Some(&[2][..]));
+    /// assert_eq!(ours.get_slice("c"), Some(&[33][..]));
+    /// assert_eq!(ours.get_slice("d"), Some(&[40][..]));
+    /// ```
+    pub fn merge_with<F>(&mut self, other: MultiMap<K, V, S>, mut resolve: F)
+    where
+        F: FnMut(&K, &[V], &[V]) -> MergeResolution<V>,
+    {
+        for (k, theirs) in other.inner {
+            match self.inner.entry(k) {
+                hash_map::Entry::Occupied(mut entry) => {
+                    match resolve(entry.key(), entry.get(), &theirs) {
+                        MergeResolution::Append => entry.get_mut().extend(theirs),
+                        MergeResolution::Replace => *entry.get_mut() = theirs,
+                        MergeResolution::KeepOurs => {}
+                        MergeResolution::Values(values) => {
+                            *entry.get_mut() = SmallVec::from(values)
+                        }
+                    }
+                }
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(theirs);
+                }
+            }
+        }
+    }
+
+    /// Moves all keys and values of `other` into the map, assuming the values of
+    /// every key are sorted in both maps. For keys which exist in both maps the
+    /// two vectors are merged so that the resulting vector is sorted as well.
+    /// Equal values are kept, with our values ordered before theirs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut ours = MultiMap::new();
+    /// ours.insert_many(1, vec![1, 4, 6]);
+    ///
+    /// let mut theirs = MultiMap::new();
+    /// theirs.insert_many(1, vec![2, 4, 5]);
+    /// theirs.insert(2, 3);
+    ///
+    /// ours.merge_sorted(theirs);
+    /// assert_eq!(ours.get_slice(&1), Some(&[1, 2, 4, 4, 5, 6][..]));
+    /// assert_eq!(ours.get_slice(&2), Some(&[3][..]));
+    /// ```
+    pub fn merge_sorted(&mut self, other: MultiMap<K, V, S>)
+    where
+        V: Ord,
+    {
+        for (k, theirs) in other.inner {
+            match self.inner.entry(k) {
+                hash_map::Entry::Occupied(mut entry) => {
+                    let ours = core::mem::take(entry.get_mut());
+                    let mut merged = SmallVec::with_capacity(ours.len() + theirs.len());
+                    let mut ours = ours.into_iter().peekable();
+                    let mut theirs = theirs.into_iter().peekable();
+                    loop {
+                        let take_ours = match (ours.peek(), theirs.peek()) {
+                            (Some(a), Some(b)) => a <= b,
+                            (Some(_), None) => true,
+                            (None, Some(_)) => false,
+                            (None, None) => break,
+                        };
+                        let next = if take_ours {
+                            ours.next()
+                        } else {
+                            theirs.next()
+                        };
+                        merged.extend(next);
+                    }
+                    *entry.get_mut() = merged;
+                }
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(theirs);
+                }
+            }
+        }
+    }
+
+    /// Splits the map into two maps by key. The first map contains all keys for
+    /// which the predicate returns true, the second map all other keys. Both maps
+    /// use a clone of the map's hash builder.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// map.insert(2, 2332);
+    /// let (multi, single) = map.partition(|_, values| values.len() > 1);
+    /// assert_eq!(multi.get_slice(&1), Some(&[42, 1337][..]));
+    /// assert_eq!(single.get_slice(&2), Some(&[2332][..]));
+    /// ```
+    pub fn partition<F>(self, mut f: F) -> (MultiMap<K, V, S>, MultiMap<K, V, S>)
+    where
+        S: Clone,
+        F: FnMut(&K, &[V]) -> bool,
+    {
+        let mut matching = MultiMap::with_hasher(self.inner.hasher().clone());
+        let mut rest = MultiMap::with_hasher(self.inner.hasher().clone());
+        for (k, values) in self.inner {
+            if f(&k, &values) {
+                matching.inner.insert(k, values);
+            } else {
+                rest.inner.insert(k, values);
+            }
+        }
+        (matching, rest)
+    }
+
+    /// Splits the map into `n` maps by the hash of each key, computed with the
+    /// map's own hash builder. Every returned map uses a clone of that hash
+    /// builder, so a key always ends up in the same shard as long as the hash
+    /// builder hashes it the same way.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// for i in 0..100 {
+    ///     map.insert(i, i);
+    /// }
+    /// let shards = map.split_by_key_hash(4);
+    /// assert_eq!(shards.len(), 4);
+    /// assert_eq!(shards.iter().map(|s| s.len()).sum::<usize>(), 100);
+    /// ```
+    pub fn split_by_key_hash(self, n: usize) -> Vec<MultiMap<K, V, S>>
+    where
+        S: Clone,
+    {
+        assert!(n > 0, "cannot split a map into zero shards");
+
+        let hash_builder = self.inner.hasher().clone();
+        let mut shards: Vec<_> = (0..n)
+            .map(|_| MultiMap::with_hasher(hash_builder.clone()))
+            .collect();
+        for (k, values) in self.inner {
+            let shard = (hash_builder.hash_one(&k) % n as u64) as usize;
+            shards[shard].inner.insert(k, values);
+        }
+        shards
+    }
+
+    /// Moves all values for which the predicate returns true into a new map,
+    /// which uses a clone of the map's hash builder. Keys which end up without
+    /// values are removed from the map. The values are moved, only the keys of
+    /// the moved values are cloned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert_many(1, vec![1, 2, 3]);
+    /// map.insert(2, 4);
+    /// let even = map.split_off_values(|_, v| v % 2 == 0);
+    /// assert_eq!(map.get_slice(&1), Some(&[1, 3][..]));
+    /// assert!(!map.contains_key(&2));
+    /// assert_eq!(even.get_slice(&1), Some(&[2][..]));
+    /// assert_eq!(even.get_slice(&2), Some(&[4][..]));
+    /// ```
+    pub fn split_off_values<F>(&mut self, mut f: F) -> MultiMap<K, V, S>
+    where
+        K: Clone,
+        S: Clone,
+        F: FnMut(&K, &V) -> bool,
+    {
+        let mut split = MultiMap::with_hasher(self.inner.hasher().clone());
+        for (k, values) in self.inner.iter_mut() {
+            let first = match values.iter().position(|v| f(k, v)) {
+                Some(first) => first,
+                None => continue,
+            };
+
+            // Only the values from the first match on are taken out, so the
+            // list keeps its buffer for the values which stay.
+            let tail: SmallVec<[V; 1]> = values.drain(first..).collect();
+            let mut moved = SmallVec::new();
+            for (i, v) in tail.into_iter().enumerate() {
+                if i == 0 || f(k, &v) {
+                    moved.push(v);
+                } else {
+                    values.push(v);
+                }
+            }
+            split.inner.insert(k.clone(), moved);
+        }
+
+        self.inner
+            .retain(|k, values| !values.is_empty() || !split.inner.contains_key(k));
+        split
+    }
 }
 
 impl<'a, K, V, S, Q: ?Sized> Index<&'a Q> for MultiMap<K, V, S>
@@ -868,10 +1653,10 @@ where
     fn extend<T: IntoIterator<Item = (K, Vec<V>)>>(&mut self, iter: T) {
         for (k, values) in iter {
             match self.inner.entry(k) {
-                std::collections::hash_map::Entry::Occupied(mut entry) => {
+                hash_map::Entry::Occupied(mut entry) => {
                     entry.get_mut().extend(values);
                 }
-                std::collections::hash_map::Entry::Vacant(entry) => {
+                hash_map::Entry::Vacant(entry) => {
                     entry.insert(SmallVec::from(values));
                 }
             }
@@ -893,6 +1678,79 @@ where
     }
 }
 
+/// An iterator over the entries of the hash table of a MultiMap. It wraps the
+/// iterator of the `std` or `hashbrown` backend, so that its type does not
+/// depend on the enabled features.
+pub struct IterAll<'a, K: 'a, V: 'a> {
+    inner: hash_map::Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Clone for IterAll<'a, K, V> {
+    fn clone(&self) -> IterAll<'a, K, V> {
+        IterAll {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K: Debug, V: Debug> Debug for IterAll<'a, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl<'a, K, V> Iterator for IterAll<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for IterAll<'a, K, V> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl<'a, K, V> FusedIterator for IterAll<'a, K, V> {}
+
+/// A mutable iterator over the entries of the hash table of a MultiMap. Like
+/// `IterAll`, its type does not depend on the enabled features.
+pub struct IterAllMut<'a, K: 'a, V: 'a> {
+    inner: hash_map::IterMut<'a, K, V>,
+}
+
+impl<'a, K: Debug, V: Debug> Debug for IterAllMut<'a, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl<'a, K, V> Iterator for IterAllMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for IterAllMut<'a, K, V> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl<'a, K, V> FusedIterator for IterAllMut<'a, K, V> {}
+
 #[derive(Clone)]
 pub struct Iter<'a, K: 'a, V: 'a> {
     inner: IterAll<'a, K, Vec<V>>,
@@ -978,33 +1836,36 @@ mod tests {
     use std::iter::FromIterator;
 
     use super::*;
+    use test_util::*;
 
     #[test]
     fn create() {
-        let _: MultiMap<usize, usize> = MultiMap {
-            inner: HashMap::new(),
+        let _: TestMap<usize, usize> = MultiMap {
+            inner: hash_map::HashMap::default(),
         };
     }
 
     #[test]
+    #[cfg(feature = "std")]
     fn new() {
         let _: MultiMap<usize, usize> = MultiMap::new();
     }
 
     #[test]
+    #[cfg(feature = "std")]
     fn with_capacity() {
         let _: MultiMap<usize, usize> = MultiMap::with_capacity(20);
     }
 
     #[test]
     fn insert() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 3);
     }
 
     #[test]
     fn insert_identical() {
-        let mut m = MultiMap::new();
+        let mut m = TestMap::default();
         m.insert(1, 42);
         m.insert(1, 42);
         assert_eq!(m.get_slice(&1), Some(&vec![42, 42][..]));
@@ -1012,14 +1873,14 @@ mod tests {
 
     #[test]
     fn insert_many() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert_many(1, vec![3, 4]);
         assert_eq!(Some(&vec![3, 4][..]), m.get_slice(&1));
     }
 
     #[test]
     fn insert_many_again() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 2);
         m.insert_many(1, vec![3, 4]);
         assert_eq!(Some(&vec![2, 3, 4][..]), m.get_slice(&1));
@@ -1027,7 +1888,7 @@ mod tests {
 
     #[test]
     fn insert_many_overlap() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert_many(1, vec![2, 3]);
         m.insert_many(1, vec![3, 4]);
         assert_eq!(Some(&vec![2, 3, 3, 4][..]), m.get_slice(&1));
@@ -1035,14 +1896,14 @@ mod tests {
 
     #[test]
     fn insert_many_from_slice() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert_many_from_slice(1, &[3, 4]);
         assert_eq!(Some(&vec![3, 4][..]), m.get_slice(&1));
     }
 
     #[test]
     fn insert_many_from_slice_again() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 2);
         m.insert_many_from_slice(1, &[3, 4]);
         assert_eq!(Some(&vec![2, 3, 4][..]), m.get_slice(&1));
@@ -1050,7 +1911,7 @@ mod tests {
 
     #[test]
     fn insert_existing() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 3);
         m.insert(1, 4);
         assert_eq!(Some(&vec![3, 4][..]), m.get_slice(&1));
@@ -1059,13 +1920,13 @@ mod tests {
     #[test]
     #[should_panic]
     fn index_no_entry() {
-        let m: MultiMap<usize, usize> = MultiMap::new();
+        let m: TestMap<usize, usize> = TestMap::default();
         let _ = &m[&1];
     }
 
     #[test]
     fn index() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         let values = m[&1];
         assert_eq!(values, 42);
@@ -1073,20 +1934,20 @@ mod tests {
 
     #[test]
     fn contains_key_true() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         assert!(m.contains_key(&1));
     }
 
     #[test]
     fn contains_key_false() {
-        let m: MultiMap<usize, usize> = MultiMap::new();
+        let m: TestMap<usize, usize> = TestMap::default();
         assert!(!m.contains_key(&1));
     }
 
     #[test]
     fn len() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         m.insert(2, 1337);
         m.insert(3, 99);
@@ -1095,35 +1956,57 @@ mod tests {
 
     #[test]
     fn remove_not_present() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         let v = m.remove(&1);
         assert!(v.is_none());
     }
 
     #[test]
     fn remove_present() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         let v = m.remove(&1);
         assert_eq!(Some(vec![42]), v.map(|i| i.collect::<_>()));
     }
 
+    #[test]
+    fn remove_entry_present() {
+        let mut m: TestMap<String, usize> = TestMap::default();
+        m.insert("a".to_string(), 42);
+        m.insert("a".to_string(), 43);
+        let (k, v) = m.remove_entry("a").unwrap();
+        assert_eq!(k, "a");
+        assert_eq!(vec![42, 43], v.collect::<Vec<_>>());
+        assert!(m.is_empty());
+    }
+
+    #[test]
+    fn remove_many() {
+        let mut m: TestMap<usize, usize> = TestMap::default();
+        m.insert(1, 42);
+        m.insert(2, 42);
+        m.insert(3, 42);
+        assert_eq!(m.remove_many(&[1, 2, 5]), 2);
+        assert_eq!(m.len(), 1);
+        assert!(m.contains_key(&3));
+    }
+
     #[test]
     fn get_not_present() {
-        let m: MultiMap<usize, usize> = MultiMap::new();
+        let m: TestMap<usize, usize> = TestMap::default();
         assert_eq!(m.get(&1), None);
     }
 
     #[test]
     fn get_present() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         assert_eq!(m.get(&1), Some(&42));
     }
 
     #[test]
     fn get_empty() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         m.remove(&1);
         assert_eq!(m.get(&1), None);
@@ -1131,13 +2014,13 @@ mod tests {
 
     #[test]
     fn get_slice_not_present() {
-        let m: MultiMap<usize, usize> = MultiMap::new();
+        let m: TestMap<usize, usize> = TestMap::default();
         assert_eq!(m.get_slice(&1), None);
     }
 
     #[test]
     fn get_slice_present() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         m.insert(1, 1337);
         assert_eq!(Some(&vec![42, 1337][..]), m.get_slice(&1));
@@ -1145,26 +2028,26 @@ mod tests {
 
     #[test]
     fn capacity() {
-        let m: MultiMap<usize, usize> = MultiMap::with_capacity(20);
+        let m: TestMap<usize, usize> = MultiMap::with_capacity_and_hasher(20, TestState::default());
         assert!(m.capacity() >= 20);
     }
 
     #[test]
     fn is_empty_true() {
-        let m: MultiMap<usize, usize> = MultiMap::new();
+        let m: TestMap<usize, usize> = TestMap::default();
         assert!(m.is_empty());
     }
 
     #[test]
     fn is_empty_false() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         assert!(!m.is_empty());
     }
 
     #[test]
     fn clear() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         m.clear();
         assert!(m.is_empty());
@@ -1172,7 +2055,7 @@ mod tests {
 
     #[test]
     fn get_mut() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         if let Some(v) = m.get_mut(&1) {
             *v = 1337;
@@ -1182,7 +2065,7 @@ mod tests {
 
     #[test]
     fn get_all_mut() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         m.insert(1, 1337);
         if let Some(mut v) = m.get_all_mut(&1) {
@@ -1196,7 +2079,7 @@ mod tests {
 
     #[test]
     fn get_slice_mut() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         m.insert(1, 1337);
         if let Some(v) = m.get_slice_mut(&1) {
@@ -1208,7 +2091,7 @@ mod tests {
 
     #[test]
     fn get_mut_empty() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         m.get_all_mut(&1).and_then(|mut v| v.pop());
         assert_eq!(m.get_mut(&1), None);
@@ -1216,7 +2099,7 @@ mod tests {
 
     #[test]
     fn keys() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         m.insert(2, 42);
         m.insert(4, 42);
@@ -1232,7 +2115,7 @@ mod tests {
 
     #[test]
     fn iter() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         m.insert(1, 42);
         m.insert(4, 42);
@@ -1247,7 +2130,7 @@ mod tests {
 
     #[test]
     fn intoiterator_for_reference_type() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         m.insert(1, 43);
         m.insert(4, 42);
@@ -1269,7 +2152,7 @@ mod tests {
     /*
     #[test]
     fn intoiterator_for_mutable_reference_type() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         m.insert(1, 43);
         m.insert(4, 42);
@@ -1294,7 +2177,7 @@ mod tests {
 
     #[test]
     fn intoiterator_consuming() {
-        let mut m: MultiMap<usize, usize> = MultiMap::new();
+        let mut m: TestMap<usize, usize> = TestMap::default();
         m.insert(1, 42);
         m.insert(1, 43);
         m.insert(4, 42);
@@ -1315,8 +2198,8 @@ mod tests {
 
     #[test]
     fn test_fmt_debug() {
-        let mut map = MultiMap::new();
-        let empty: MultiMap<i32, i32> = MultiMap::new();
+        let mut map = TestMap::default();
+        let empty: TestMap<i32, i32> = TestMap::default();
 
         map.insert(1, 2);
         map.insert(1, 5);
@@ -1331,11 +2214,11 @@ mod tests {
 
     #[test]
     fn test_eq() {
-        let mut m1 = MultiMap::new();
+        let mut m1 = TestMap::default();
         m1.insert(1, 2);
         m1.insert(2, 3);
         m1.insert(3, 4);
-        let mut m2 = MultiMap::new();
+        let mut m2 = TestMap::default();
         m2.insert(1, 2);
         m2.insert(2, 3);
         assert_ne!(m1, m2);
@@ -1349,10 +2232,10 @@ mod tests {
 
     #[test]
     fn test_eq_empty_key() {
-        let mut m1 = MultiMap::new();
+        let mut m1 = TestMap::default();
         m1.insert(1, 2);
         m1.insert(2, 3);
-        let mut m2 = MultiMap::new();
+        let mut m2 = TestMap::default();
         m2.insert(1, 2);
         m2.insert_many(2, []);
         assert_ne!(m1, m2);
@@ -1362,13 +2245,13 @@ mod tests {
 
     #[test]
     fn test_default() {
-        let _: MultiMap<u8, u8> = Default::default();
+        let _: TestMap<u8, u8> = Default::default();
     }
 
     #[test]
     fn test_from_iterator() {
         let vals: Vec<(&str, i64)> = vec![("foo", 123), ("bar", 456), ("foo", 789)];
-        let multimap: MultiMap<&str, i64> = MultiMap::from_iter(vals);
+        let multimap: TestMap<&str, i64> = MultiMap::from_iter(vals);
 
         let foo_vals: &[i64] = multimap.get_slice("foo").unwrap();
         assert!(foo_vals.contains(&123));
@@ -1380,7 +2263,7 @@ mod tests {
 
     #[test]
     fn test_extend_consuming_hashmap() {
-        let mut a = MultiMap::new();
+        let mut a = TestMap::default();
         a.insert(1, 42);
 
         let mut b = HashMap::new();
@@ -1395,7 +2278,7 @@ mod tests {
 
     #[test]
     fn test_extend_ref_hashmap() {
-        let mut a = MultiMap::new();
+        let mut a = TestMap::default();
         a.insert(1, 42);
 
         let mut b = HashMap::new();
@@ -1413,10 +2296,10 @@ mod tests {
     /*
     #[test]
     fn test_extend_consuming_multimap() {
-        let mut a = MultiMap::new();
+        let mut a = TestMap::default();
         a.insert(1, 42);
 
-        let mut b = MultiMap::new();
+        let mut b = TestMap::default();
         b.insert(1, 43);
         b.insert(1, 44);
         b.insert(2, 666);
@@ -1429,10 +2312,10 @@ mod tests {
 
     #[test]
     fn test_extend_ref_multimap() {
-        let mut a = MultiMap::new();
+        let mut a = TestMap::default();
         a.insert(1, 42);
 
-        let mut b = MultiMap::new();
+        let mut b = TestMap::default();
         b.insert(1, 43);
         b.insert(1, 44);
         b.insert(2, 666);
@@ -1447,7 +2330,7 @@ mod tests {
 
     #[test]
     fn test_entry() {
-        let mut m = MultiMap::new();
+        let mut m = TestMap::default();
         m.insert(1, 42);
 
         {
@@ -1463,7 +2346,7 @@ mod tests {
 
     #[test]
     fn test_entry_vec() {
-        let mut m = MultiMap::new();
+        let mut m = TestMap::default();
         m.insert(1, 42);
 
         {
@@ -1480,7 +2363,7 @@ mod tests {
 
     #[test]
     fn test_is_vec() {
-        let mut m = MultiMap::new();
+        let mut m = TestMap::default();
         m.insert(1, 42);
         m.insert(1, 1337);
         m.insert(2, 2332);
@@ -1491,6 +2374,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "std")]
     fn test_macro() {
         let mut manual_map = MultiMap::new();
         manual_map.insert("key1", 42);
@@ -1508,7 +2392,7 @@ mod tests {
 
     #[test]
     fn retain_removes_element() {
-        let mut m = MultiMap::new();
+        let mut m = TestMap::default();
         m.insert(1, 42);
         m.insert(1, 99);
         m.retain(|&k, &v| k == 1 && v == 42);
@@ -1518,7 +2402,7 @@ mod tests {
 
     #[test]
     fn retain_also_removes_empty_vector() {
-        let mut m = MultiMap::new();
+        let mut m = TestMap::default();
         m.insert(1, 42);
         m.insert(1, 99);
         m.insert(2, 42);
@@ -1526,4 +2410,189 @@ mod tests {
         assert_eq!(1, m.len());
         assert_eq!(Some(&42), m.get(&1));
     }
+
+    #[test]
+    fn retain_keys() {
+        let mut m = TestMap::default();
+        m.insert(1, 42);
+        m.insert(1, 99);
+        m.insert(2, 42);
+        m.retain_keys(|&k, v| k == 1 && v == [42, 99]);
+        assert_eq!(1, m.len());
+        assert_eq!(Some(&[42, 99][..]), m.get_slice(&1));
+    }
+
+    #[test]
+    fn sort_values() {
+        let mut m = TestMap::default();
+        m.insert(1, 3);
+        m.insert(1, 1);
+        m.insert(1, 2);
+        m.insert(2, 5);
+        m.sort_values();
+        assert_eq!(Some(&[1, 2, 3][..]), m.get_slice(&1));
+        assert_eq!(Some(&[5][..]), m.get_slice(&2));
+    }
+
+    #[test]
+    fn dedup_values_shrinks_inline() {
+        let mut m: TestMap<usize, usize> = TestMap::default();
+        m.insert_many(1, vec![42, 42, 42]);
+        assert!(m.inner[&1].spilled());
+        m.dedup_values();
+        assert_eq!(Some(&[42][..]), m.get_slice(&1));
+        assert!(!m.inner[&1].spilled());
+    }
+
+    #[test]
+    fn normalize() {
+        let mut m = TestMap::default();
+        m.insert_many(1, vec![3, 1, 3, 2, 1]);
+        m.normalize();
+        assert_eq!(Some(&[1, 2, 3][..]), m.get_slice(&1));
+    }
+
+    #[test]
+    fn rename_key() {
+        let mut m = TestMap::default();
+        m.insert(1, 42);
+        m.insert(2, 1337);
+        assert!(!m.rename_key(&3, 4, MergePolicy::Append));
+        assert!(m.rename_key(&1, 2, MergePolicy::Replace));
+        assert_eq!(m.len(), 1);
+        assert_eq!(Some(&[42][..]), m.get_slice(&2));
+        m.insert(1, 99);
+        assert!(m.rename_key(&1, 2, MergePolicy::Keep));
+        assert_eq!(m.len(), 1);
+        assert_eq!(Some(&[42][..]), m.get_slice(&2));
+    }
+
+    #[test]
+    fn move_values() {
+        let mut m = TestMap::default();
+        m.insert_many(1, vec![1, 2, 3]);
+        m.insert(2, 0);
+        assert_eq!(m.move_values(&1, &3, |_| true), 0);
+        assert_eq!(Some(&[1, 2, 3][..]), m.get_slice(&1));
+        assert_eq!(m.move_values(&1, &2, |_| true), 3);
+        assert!(!m.contains_key(&1));
+        assert_eq!(Some(&[0, 1, 2, 3][..]), m.get_slice(&2));
+    }
+
+    #[test]
+    fn swap_keys_missing() {
+        let mut m = TestMap::default();
+        m.insert(1, 42);
+        assert!(!m.swap_keys(&1, &2));
+        assert_eq!(Some(&[42][..]), m.get_slice(&1));
+        assert!(!m.contains_key(&2));
+    }
+
+    #[test]
+    fn append_key_missing() {
+        let mut m = TestMap::default();
+        m.insert(1, 42);
+        assert!(!m.append_key(&1, &2));
+        assert_eq!(Some(&[42][..]), m.get_slice(&1));
+    }
+
+    #[test]
+    fn merge_with() {
+        let mut a = TestMap::default();
+        a.insert(1, 1);
+        a.insert(2, 2);
+        let mut b = TestMap::default();
+        b.insert(1, 10);
+        b.insert(2, 20);
+        b.insert(3, 30);
+        a.merge_with(b, |&k, _, _| {
+            if k == 1 {
+                MergeResolution::Replace
+            } else {
+                MergeResolution::Append
+            }
+        });
+        assert_eq!(a.len(), 3);
+        assert_eq!(Some(&[10][..]), a.get_slice(&1));
+        assert_eq!(Some(&[2, 20][..]), a.get_slice(&2));
+        assert_eq!(Some(&[30][..]), a.get_slice(&3));
+    }
+
+    #[test]
+    fn merge_sorted() {
+        let mut a = TestMap::default();
+        a.insert_many(1, vec![1, 3, 5]);
+        let mut b = TestMap::default();
+        b.insert_many(1, vec![0, 2, 6, 7]);
+        a.merge_sorted(b);
+        assert_eq!(Some(&[0, 1, 2, 3, 5, 6, 7][..]), a.get_slice(&1));
+    }
+
+    #[test]
+    fn partition() {
+        let mut m = TestMap::default();
+        m.insert(1, 42);
+        m.insert(2, 42);
+        m.insert(3, 42);
+        let (odd, even) = m.partition(|k, _| k % 2 == 1);
+        assert_eq!(odd.len(), 2);
+        assert_eq!(even.len(), 1);
+        assert!(even.contains_key(&2));
+    }
+
+    #[test]
+    fn split_by_key_hash_is_stable() {
+        let mut m = TestMap::default();
+        for i in 0..50 {
+            m.insert(i, i);
+            m.insert(i, i + 1);
+        }
+        let hash_builder = m.inner.hasher().clone();
+        let shards = m.split_by_key_hash(3);
+        for (i, shard) in shards.iter().enumerate() {
+            for k in shard.keys() {
+                assert_eq!((hash_builder.hash_one(k) % 3) as usize, i);
+                assert_eq!(Some(&[*k, *k + 1][..]), shard.get_slice(k));
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn split_by_key_hash_zero() {
+        let m: TestMap<usize, usize> = TestMap::default();
+        m.split_by_key_hash(0);
+    }
+
+    #[test]
+    fn split_off_values_keeps_buffers() {
+        let mut m: TestMap<usize, usize> = TestMap::default();
+        m.insert_many(1, vec![1, 3, 5, 7]);
+        m.insert_many(2, vec![1, 2, 3, 4, 5]);
+        let untouched = (m.inner[&1].as_ptr(), m.inner[&1].capacity());
+        let touched = (m.inner[&2].as_ptr(), m.inner[&2].capacity());
+
+        let mut calls = 0;
+        let split = m.split_off_values(|_, v| {
+            calls += 1;
+            v % 2 == 0
+        });
+        assert_eq!(calls, 9);
+        assert_eq!((m.inner[&1].as_ptr(), m.inner[&1].capacity()), untouched);
+        assert_eq!((m.inner[&2].as_ptr(), m.inner[&2].capacity()), touched);
+        assert_eq!(Some(&[1, 3, 5][..]), m.get_slice(&2));
+        assert_eq!(Some(&[2, 4][..]), split.get_slice(&2));
+        assert!(!split.contains_key(&1));
+    }
+
+    #[test]
+    fn split_off_values_keeps_empty_keys() {
+        let mut m: TestMap<usize, usize> = TestMap::default();
+        m.insert_many(1, vec![]);
+        m.insert(2, 42);
+        let split = m.split_off_values(|_, _| true);
+        assert!(m.contains_key(&1));
+        assert!(!m.contains_key(&2));
+        assert_eq!(Some(&[42][..]), split.get_slice(&2));
+    }
 }
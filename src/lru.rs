@@ -0,0 +1,464 @@
+// Copyright (c) 2016 multimap developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! A MultiMap which evicts its least recently used keys.
+
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt::{self, Debug};
+use core::hash::{BuildHasher, Hash};
+use core::iter;
+#[cfg(feature = "std")]
+use std::collections::hash_map::RandomState;
+
+use hash_map::HashMap;
+use smallvec::{smallvec, SmallVec};
+
+/// The limit of a LruMultiMap, above which keys are evicted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LruLimit {
+    /// The maximum number of keys.
+    Keys(usize),
+
+    /// The maximum total number of values.
+    Values(usize),
+}
+
+/// The index of no node, ending the recency list.
+const NIL: usize = usize::MAX;
+
+/// A key with its values, linked to the keys used just before and after it.
+#[derive(Clone)]
+struct LruNode<K, V, const N: usize> {
+    key: K,
+    values: SmallVec<[V; N]>,
+    prev: usize,
+    next: usize,
+}
+
+/// A map which allows storing multiple values per key, and evicts whole keys
+/// in least recently used order when it grows beyond its `LruLimit`.
+///
+/// Inserting values for a key and reading them with `get` or `get_slice`
+/// marks the key as most recently used, while `peek` and `contains_key` do
+/// not. `insert` returns the evicted keys with their values, and
+/// `insert_with` hands them to a callback instead. A key whose own values
+/// exceed a `LruLimit::Values` limit is evicted as well, after all other keys.
+///
+/// The keys and their values are stored in a slab of nodes, which are linked
+/// in recency order, so marking a key as used takes constant time. The values
+/// of each key are stored in a `SmallVec<[V; N]>`, like in a MultiMap. The
+/// hash table maps each key to its node, and so holds a second copy of every
+/// key, which is why keys must implement `Clone`.
+///
+/// # Examples
+///
+/// ```
+/// use multimap::{LruLimit, LruMultiMap};
+///
+/// let mut cache: LruMultiMap<_, _> = LruMultiMap::new(LruLimit::Keys(2));
+/// cache.insert("a", 1);
+/// cache.insert("b", 2);
+/// cache.insert("a", 3);
+/// assert_eq!(cache.get_slice("b"), Some(&[2][..]));
+///
+/// let evicted = cache.insert("c", 4);
+/// assert_eq!(evicted, [("a", vec![1, 3])]);
+/// assert_eq!(cache.peek("c"), Some(&[4][..]));
+/// ```
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct LruMultiMap<K, V, S = RandomState, const N: usize = 1> {
+    map: HashMap<K, usize, S>,
+    nodes: Vec<LruNode<K, V, N>>,
+    head: usize,
+    tail: usize,
+    limit: LruLimit,
+    values: usize,
+}
+
+/// A map which allows storing multiple values per key, and evicts whole keys
+/// in least recently used order.
+#[cfg(not(feature = "std"))]
+#[derive(Clone)]
+pub struct LruMultiMap<K, V, S, const N: usize = 1> {
+    map: HashMap<K, usize, S>,
+    nodes: Vec<LruNode<K, V, N>>,
+    head: usize,
+    tail: usize,
+    limit: LruLimit,
+    values: usize,
+}
+
+#[cfg(feature = "std")]
+impl<K, V, const N: usize> LruMultiMap<K, V, RandomState, N>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Creates an empty map with the given limit.
+    pub fn new(limit: LruLimit) -> LruMultiMap<K, V, RandomState, N> {
+        LruMultiMap::with_hasher(limit, RandomState::new())
+    }
+}
+
+impl<K, V, S, const N: usize> LruMultiMap<K, V, S, N>
+where
+    K: Eq + Hash + Clone,
+    S: BuildHasher,
+{
+    /// Creates an empty map with the given limit, which will use the given
+    /// hash builder to hash keys.
+    pub fn with_hasher(limit: LruLimit, hash_builder: S) -> LruMultiMap<K, V, S, N> {
+        LruMultiMap {
+            map: HashMap::with_hasher(hash_builder),
+            nodes: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            limit,
+            values: 0,
+        }
+    }
+
+    /// Returns the limit of the map.
+    pub fn limit(&self) -> LruLimit {
+        self.limit
+    }
+
+    /// Returns the number of keys in the map.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns true if the map contains no keys.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the total number of values in the map.
+    pub fn total_values(&self) -> usize {
+        self.values
+    }
+
+    /// Unlinks a node from the recency list.
+    fn unlink(&mut self, i: usize) {
+        let (prev, next) = (self.nodes[i].prev, self.nodes[i].next);
+        match prev {
+            NIL => self.head = next,
+            prev => self.nodes[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.nodes[next].prev = prev,
+        }
+    }
+
+    /// Links an unlinked node as the most recently used.
+    fn push_back(&mut self, i: usize) {
+        self.nodes[i].prev = self.tail;
+        self.nodes[i].next = NIL;
+        match self.tail {
+            NIL => self.head = i,
+            tail => self.nodes[tail].next = i,
+        }
+        self.tail = i;
+    }
+
+    /// Marks a node as the most recently used.
+    fn touch(&mut self, i: usize) {
+        if i != self.tail {
+            self.unlink(i);
+            self.push_back(i);
+        }
+    }
+
+    /// Removes an unlinked node from the slab, moving the last node into its
+    /// place.
+    fn take_node(&mut self, i: usize) -> LruNode<K, V, N> {
+        let node = self.nodes.swap_remove(i);
+        if i < self.nodes.len() {
+            let (prev, next) = (self.nodes[i].prev, self.nodes[i].next);
+            match prev {
+                NIL => self.head = i,
+                prev => self.nodes[prev].next = i,
+            }
+            match next {
+                NIL => self.tail = i,
+                next => self.nodes[next].prev = i,
+            }
+            *self
+                .map
+                .get_mut(&self.nodes[i].key)
+                .expect("key is in the map") = i;
+        }
+        self.values -= node.values.len();
+        node
+    }
+
+    /// Inserts a key-value pair into the map and marks the key as most
+    /// recently used. While the map exceeds its limit, the least recently
+    /// used key is removed. The removed keys are returned with their values,
+    /// from least to most recently used.
+    pub fn insert(&mut self, k: K, v: V) -> Vec<(K, Vec<V>)> {
+        let mut evicted = Vec::new();
+        self.insert_with(k, v, |key, values| evicted.push((key, values)));
+        evicted
+    }
+
+    /// Inserts a key-value pair into the map and marks the key as most
+    /// recently used. While the map exceeds its limit, the least recently
+    /// used key is removed and passed to `on_evict` with its values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::{LruLimit, LruMultiMap};
+    ///
+    /// let mut cache: LruMultiMap<_, _> = LruMultiMap::new(LruLimit::Values(2));
+    /// let mut evicted = 0;
+    /// for i in 0..5 {
+    ///     cache.insert_with(i, i, |_, values| evicted += values.len());
+    /// }
+    /// assert_eq!(evicted, 3);
+    /// ```
+    pub fn insert_with<F>(&mut self, k: K, v: V, mut on_evict: F)
+    where
+        F: FnMut(K, Vec<V>),
+    {
+        match self.map.get(&k) {
+            Some(&i) => {
+                self.nodes[i].values.push(v);
+                self.touch(i);
+            }
+            None => {
+                let i = self.nodes.len();
+                self.nodes.push(LruNode {
+                    key: k.clone(),
+                    values: smallvec![v],
+                    prev: NIL,
+                    next: NIL,
+                });
+                self.push_back(i);
+                self.map.insert(k, i);
+            }
+        }
+        self.values += 1;
+
+        while self.over_limit() {
+            let (key, values) = self.pop_lru().expect("an empty map is within its limit");
+            on_evict(key, values);
+        }
+    }
+
+    fn over_limit(&self) -> bool {
+        match self.limit {
+            LruLimit::Keys(max) => self.nodes.len() > max,
+            LruLimit::Values(max) => self.values > max,
+        }
+    }
+
+    /// Returns a reference to the first value of the key, and marks the key
+    /// as most recently used.
+    pub fn get<Q>(&mut self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.get_slice(k)?.first()
+    }
+
+    /// Returns the values of the key, and marks the key as most recently
+    /// used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::{LruLimit, LruMultiMap};
+    ///
+    /// let mut cache: LruMultiMap<_, _> = LruMultiMap::new(LruLimit::Values(2));
+    /// cache.insert(1, 'a');
+    /// cache.insert(2, 'b');
+    /// cache.get_slice(&1);
+    /// cache.insert(3, 'c');
+    /// assert!(cache.contains_key(&1));
+    /// assert!(!cache.contains_key(&2));
+    /// ```
+    pub fn get_slice<Q>(&mut self, k: &Q) -> Option<&[V]>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let i = *self.map.get(k)?;
+        self.touch(i);
+        Some(&self.nodes[i].values[..])
+    }
+
+    /// Returns the values of the key without changing its recency.
+    pub fn peek<Q>(&self, k: &Q) -> Option<&[V]>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.map.get(k).map(|&i| &self.nodes[i].values[..])
+    }
+
+    /// Returns true if the map contains the key, without changing its
+    /// recency.
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.map.contains_key(k)
+    }
+
+    /// Removes a key from the map, returning its values if the key was in the
+    /// map.
+    pub fn remove<Q>(&mut self, k: &Q) -> Option<impl Iterator<Item = V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let i = self.map.remove(k)?;
+        self.unlink(i);
+        Some(self.take_node(i).values.into_iter())
+    }
+
+    /// Removes the least recently used key, returning it with its values.
+    pub fn pop_lru(&mut self) -> Option<(K, Vec<V>)> {
+        let i = match self.head {
+            NIL => return None,
+            head => head,
+        };
+        self.unlink(i);
+        self.map.remove(&self.nodes[i].key);
+        let node = self.take_node(i);
+        Some((node.key, node.values.into_vec()))
+    }
+
+    /// Returns the keys from least to most recently used.
+    pub fn keys_lru(&self) -> impl Iterator<Item = &K> {
+        let head = Some(self.head).filter(|&i| i != NIL);
+        iter::successors(head, move |&i| {
+            Some(self.nodes[i].next).filter(|&i| i != NIL)
+        })
+        .map(move |i| &self.nodes[i].key)
+    }
+
+    /// Removes all keys and values.
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.nodes.clear();
+        self.head = NIL;
+        self.tail = NIL;
+        self.values = 0;
+    }
+
+    /// An iterator visiting all keys in arbitrary order, with the values of
+    /// each key, without changing their recency.
+    pub fn iter_all(&self) -> impl Iterator<Item = (&K, &[V])> {
+        self.nodes.iter().map(|node| (&node.key, &node.values[..]))
+    }
+}
+
+impl<K, V, S, const N: usize> Debug for LruMultiMap<K, V, S, N>
+where
+    K: Eq + Hash + Clone + Debug,
+    V: Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter_all()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::*;
+
+    #[test]
+    fn key_limit() {
+        let mut map: LruMultiMap<_, _, TestState> =
+            LruMultiMap::with_hasher(LruLimit::Keys(3), TestState::default());
+        for i in 0..3 {
+            assert!(map.insert(i, i).is_empty());
+        }
+        assert_eq!(map.peek(&0), Some(&[0][..]));
+        assert_eq!(map.get(&1), Some(&1));
+
+        let mut evicted = map.insert(3, 3);
+        map.insert_with(4, 4, |k, v| evicted.push((k, v)));
+        assert_eq!(evicted, [(0, vec![0]), (2, vec![2])]);
+        assert_eq!(map.keys_lru().collect::<Vec<_>>(), [&1, &3, &4]);
+        assert_eq!(map.total_values(), 3);
+    }
+
+    #[test]
+    fn value_limit() {
+        let mut map: LruMultiMap<_, _, TestState> =
+            LruMultiMap::with_hasher(LruLimit::Values(4), TestState::default());
+        map.insert("a", 1);
+        map.insert("a", 2);
+        map.insert("b", 3);
+        map.insert("b", 4);
+
+        let mut evicted = Vec::new();
+        map.insert_with("b", 5, |k, v| evicted.push((k, v)));
+        assert_eq!(evicted, [("a", vec![1, 2])]);
+        assert_eq!(map.total_values(), 3);
+
+        map.insert_with("b", 6, |k, v| evicted.push((k, v)));
+        map.insert_with("b", 7, |k, v| evicted.push((k, v)));
+        assert_eq!(evicted[1], ("b", vec![3, 4, 5, 6, 7]));
+        assert!(map.is_empty());
+        assert_eq!(map.total_values(), 0);
+    }
+
+    #[test]
+    fn remove_and_pop() {
+        let mut map: LruMultiMap<_, _, TestState> =
+            LruMultiMap::with_hasher(LruLimit::Keys(10), TestState::default());
+        map.insert(1, 'a');
+        map.insert(2, 'b');
+        map.insert(1, 'c');
+        assert_eq!(map.remove(&1).unwrap().collect::<Vec<_>>(), ['a', 'c']);
+        assert_eq!(map.pop_lru(), Some((2, vec!['b'])));
+        assert_eq!(map.pop_lru(), None);
+        assert_eq!(map.total_values(), 0);
+    }
+
+    #[test]
+    fn order_after_removals() {
+        let mut map: LruMultiMap<u32, u32, TestState, 2> =
+            LruMultiMap::with_hasher(LruLimit::Keys(100), TestState::default());
+        for i in 0..10 {
+            map.insert(i, i);
+            map.insert(i, i * 10);
+        }
+        map.get_slice(&0);
+        map.get_slice(&5);
+        assert!(map.remove(&3).is_some());
+        assert!(map.remove(&9).is_some());
+        assert!(map.remove(&42).is_none());
+        assert_eq!(map.pop_lru(), Some((1, vec![1, 10])));
+
+        let keys: Vec<_> = map.keys_lru().cloned().collect();
+        assert_eq!(keys, [2, 4, 6, 7, 8, 0, 5]);
+        for k in keys {
+            assert_eq!(map.get_slice(&k), Some(&[k, k * 10][..]));
+        }
+        assert_eq!(map.keys_lru().last(), Some(&5));
+        assert_eq!(map.len(), 7);
+        assert_eq!(map.total_values(), 14);
+
+        map.clear();
+        assert_eq!(map.keys_lru().count(), 0);
+        map.insert(1, 1);
+        assert_eq!(map.keys_lru().collect::<Vec<_>>(), [&1]);
+    }
+}
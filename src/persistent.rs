@@ -0,0 +1,752 @@
+// Copyright (c) 2016 multimap developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! A persistent MultiMap which shares structure between versions.
+
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt::{self, Debug};
+use core::hash::{BuildHasher, Hash};
+use core::iter::FromIterator;
+use core::slice;
+#[cfg(feature = "std")]
+use std::collections::hash_map::RandomState;
+
+use smallvec::{smallvec, SmallVec};
+
+const BITS: u32 = 5;
+const MASK: u64 = (1 << BITS) - 1;
+
+/// All keys of a leaf share the same full hash.
+#[derive(Clone)]
+struct Leaf<K, V, const N: usize> {
+    hash: u64,
+    entries: SmallVec<[(K, SmallVec<[V; N]>); 1]>,
+}
+
+enum Child<K, V, const N: usize> {
+    Leaf(Arc<Leaf<K, V, N>>),
+    Branch(Arc<Branch<K, V, N>>),
+}
+
+struct Branch<K, V, const N: usize> {
+    bitmap: u32,
+    children: Vec<Child<K, V, N>>,
+}
+
+impl<K, V, const N: usize> Clone for Child<K, V, N> {
+    fn clone(&self) -> Child<K, V, N> {
+        match self {
+            Child::Leaf(leaf) => Child::Leaf(Arc::clone(leaf)),
+            Child::Branch(branch) => Child::Branch(Arc::clone(branch)),
+        }
+    }
+}
+
+impl<K, V, const N: usize> Clone for Branch<K, V, N> {
+    fn clone(&self) -> Branch<K, V, N> {
+        Branch {
+            bitmap: self.bitmap,
+            children: self.children.clone(),
+        }
+    }
+}
+
+fn bit(hash: u64, shift: u32) -> u32 {
+    1 << ((hash >> shift) & MASK)
+}
+
+fn position(bitmap: u32, bit: u32) -> usize {
+    (bitmap & (bit - 1)).count_ones() as usize
+}
+
+impl<K, V, const N: usize> Branch<K, V, N> {
+    fn empty() -> Branch<K, V, N> {
+        Branch {
+            bitmap: 0,
+            children: Vec::new(),
+        }
+    }
+
+    /// Creates the branch at `shift` holding two leaves with different hashes.
+    fn pair(shift: u32, a: Arc<Leaf<K, V, N>>, b: Arc<Leaf<K, V, N>>) -> Branch<K, V, N> {
+        let bit_a = bit(a.hash, shift);
+        let bit_b = bit(b.hash, shift);
+        if bit_a == bit_b {
+            Branch {
+                bitmap: bit_a,
+                children: vec![Child::Branch(Arc::new(Branch::pair(shift + BITS, a, b)))],
+            }
+        } else if bit_a < bit_b {
+            Branch {
+                bitmap: bit_a | bit_b,
+                children: vec![Child::Leaf(a), Child::Leaf(b)],
+            }
+        } else {
+            Branch {
+                bitmap: bit_a | bit_b,
+                children: vec![Child::Leaf(b), Child::Leaf(a)],
+            }
+        }
+    }
+
+    fn get<Q>(&self, hash: u64, k: &Q) -> Option<&SmallVec<[V; N]>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+    {
+        let mut branch = self;
+        let mut shift = 0;
+        loop {
+            let bit = bit(hash, shift);
+            if branch.bitmap & bit == 0 {
+                return None;
+            }
+            match &branch.children[position(branch.bitmap, bit)] {
+                Child::Leaf(leaf) if leaf.hash == hash => {
+                    return leaf
+                        .entries
+                        .iter()
+                        .find(|(key, _)| key.borrow() == k)
+                        .map(|(_, values)| values);
+                }
+                Child::Leaf(_) => return None,
+                Child::Branch(child) => {
+                    branch = child;
+                    shift += BITS;
+                }
+            }
+        }
+    }
+
+    /// Returns the new branch with the value added, and whether the key is new.
+    fn insert(&self, hash: u64, shift: u32, k: K, v: V) -> (Branch<K, V, N>, bool)
+    where
+        K: Eq + Clone,
+        V: Clone,
+    {
+        let bit = bit(hash, shift);
+        let pos = position(self.bitmap, bit);
+        let mut branch = self.clone();
+
+        if self.bitmap & bit == 0 {
+            let leaf = Leaf {
+                hash,
+                entries: smallvec![(k, smallvec![v])],
+            };
+            branch.bitmap |= bit;
+            branch.children.insert(pos, Child::Leaf(Arc::new(leaf)));
+            return (branch, true);
+        }
+
+        let (child, added) = match &self.children[pos] {
+            Child::Leaf(leaf) if leaf.hash == hash => {
+                let mut leaf = (**leaf).clone();
+                let added = match leaf.entries.iter_mut().find(|(key, _)| *key == k) {
+                    Some((_, values)) => {
+                        values.push(v);
+                        false
+                    }
+                    None => {
+                        leaf.entries.push((k, smallvec![v]));
+                        true
+                    }
+                };
+                (Child::Leaf(Arc::new(leaf)), added)
+            }
+            Child::Leaf(leaf) => {
+                let new_leaf = Leaf {
+                    hash,
+                    entries: smallvec![(k, smallvec![v])],
+                };
+                let pair = Branch::pair(shift + BITS, Arc::clone(leaf), Arc::new(new_leaf));
+                (Child::Branch(Arc::new(pair)), true)
+            }
+            Child::Branch(child) => {
+                let (child, added) = child.insert(hash, shift + BITS, k, v);
+                (Child::Branch(Arc::new(child)), added)
+            }
+        };
+        branch.children[pos] = child;
+        (branch, added)
+    }
+
+    /// Replaces the values of the key with the result of `f`, where `None`
+    /// removes the key. Returns `None` if the key is missing or `f` leaves the
+    /// values unchanged, otherwise the new branch and whether the key was removed.
+    fn modify<Q, F>(&self, hash: u64, shift: u32, k: &Q, f: F) -> Option<(Branch<K, V, N>, bool)>
+    where
+        K: Borrow<Q> + Clone,
+        V: Clone,
+        Q: ?Sized + Eq,
+        F: FnOnce(&SmallVec<[V; N]>) -> Option<Option<SmallVec<[V; N]>>>,
+    {
+        let bit = bit(hash, shift);
+        if self.bitmap & bit == 0 {
+            return None;
+        }
+        let pos = position(self.bitmap, bit);
+
+        let (child, removed) = match &self.children[pos] {
+            Child::Leaf(leaf) => {
+                if leaf.hash != hash {
+                    return None;
+                }
+                let index = leaf.entries.iter().position(|(key, _)| key.borrow() == k)?;
+                let mut replacement = f(&leaf.entries[index].1)?;
+                let removed = replacement.is_none();
+                let entries: SmallVec<_> = leaf
+                    .entries
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, (key, values))| {
+                        if i == index {
+                            replacement.take().map(|values| (key.clone(), values))
+                        } else {
+                            Some((key.clone(), values.clone()))
+                        }
+                    })
+                    .collect();
+                let child = if entries.is_empty() {
+                    None
+                } else {
+                    Some(Child::Leaf(Arc::new(Leaf { hash, entries })))
+                };
+                (child, removed)
+            }
+            Child::Branch(child) => {
+                let (child, removed) = child.modify(hash, shift + BITS, k, f)?;
+                let child = match child.children.len() {
+                    0 => None,
+                    1 if matches!(child.children[0], Child::Leaf(_)) => {
+                        Some(child.children[0].clone())
+                    }
+                    _ => Some(Child::Branch(Arc::new(child))),
+                };
+                (child, removed)
+            }
+        };
+
+        let mut branch = self.clone();
+        match child {
+            Some(child) => branch.children[pos] = child,
+            None => {
+                branch.children.remove(pos);
+                branch.bitmap &= !bit;
+            }
+        }
+        Some((branch, removed))
+    }
+}
+
+/// A persistent map which allows storing multiple values per key.
+///
+/// Updating the map returns a new map and leaves the original untouched. The
+/// new map shares all parts of the underlying hash array mapped trie which are
+/// not affected by the update with the original, so updates only copy a path
+/// of small nodes and the values of the updated key. Cloning the map is O(1).
+///
+/// # Examples
+///
+/// ```
+/// use multimap::PersistentMultiMap;
+///
+/// let v1 = PersistentMultiMap::new().insert("key", 42);
+/// let v2 = v1.insert("key", 1337);
+/// let v3 = v2.remove("key");
+///
+/// assert_eq!(v1.get_slice("key"), Some(&[42][..]));
+/// assert_eq!(v2.get_slice("key"), Some(&[42, 1337][..]));
+/// assert!(v3.is_empty());
+/// ```
+#[cfg(feature = "std")]
+pub struct PersistentMultiMap<K, V, S = RandomState, const N: usize = 1> {
+    root: Arc<Branch<K, V, N>>,
+    len: usize,
+    hash_builder: S,
+}
+
+/// An immutable map which allows storing multiple values per key.
+#[cfg(not(feature = "std"))]
+pub struct PersistentMultiMap<K, V, S, const N: usize = 1> {
+    root: Arc<Branch<K, V, N>>,
+    len: usize,
+    hash_builder: S,
+}
+
+#[cfg(feature = "std")]
+impl<K, V> PersistentMultiMap<K, V>
+where
+    K: Eq + Hash,
+{
+    /// Creates an empty PersistentMultiMap
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::PersistentMultiMap;
+    ///
+    /// let map: PersistentMultiMap<&str, isize> = PersistentMultiMap::new();
+    /// ```
+    pub fn new() -> PersistentMultiMap<K, V> {
+        PersistentMultiMap::with_hasher(RandomState::new())
+    }
+}
+
+impl<K, V, S, const N: usize> PersistentMultiMap<K, V, S, N>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Creates an empty PersistentMultiMap which will use the given hash builder to hash keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::PersistentMultiMap;
+    /// use std::collections::hash_map::RandomState;
+    ///
+    /// let s = RandomState::new();
+    /// let map: PersistentMultiMap<&str, isize> = PersistentMultiMap::with_hasher(s);
+    /// ```
+    pub fn with_hasher(hash_builder: S) -> PersistentMultiMap<K, V, S, N> {
+        PersistentMultiMap {
+            root: Arc::new(Branch::empty()),
+            len: 0,
+            hash_builder,
+        }
+    }
+
+    /// Returns the number of keys in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::PersistentMultiMap;
+    ///
+    /// let map = PersistentMultiMap::new().insert(1, 42).insert(1, 1337).insert(2, 2332);
+    /// assert_eq!(map.len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the map contains no keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::PersistentMultiMap;
+    ///
+    /// let map = PersistentMultiMap::new();
+    /// assert!(map.is_empty());
+    /// assert!(!map.insert(1, 42).is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if the map contains a value for the specified key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::PersistentMultiMap;
+    ///
+    /// let map = PersistentMultiMap::new().insert(1, 42);
+    /// assert!(map.contains_key(&1));
+    /// assert!(!map.contains_key(&2));
+    /// ```
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.get_slice(k).is_some()
+    }
+
+    /// Returns a reference to the first item in the vector corresponding to
+    /// the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::PersistentMultiMap;
+    ///
+    /// let map = PersistentMultiMap::new().insert(1, 42).insert(1, 1337);
+    /// assert_eq!(map.get(&1), Some(&42));
+    /// ```
+    pub fn get<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.get_slice(k)?.first()
+    }
+
+    /// Returns a reference to the vector corresponding to the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::PersistentMultiMap;
+    ///
+    /// let map = PersistentMultiMap::new().insert(1, 42).insert(1, 1337);
+    /// assert_eq!(map.get_slice(&1), Some(&[42, 1337][..]));
+    /// ```
+    pub fn get_slice<Q>(&self, k: &Q) -> Option<&[V]>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let hash = self.hash_builder.hash_one(k);
+        self.root.get(hash, k).map(|values| values.as_slice())
+    }
+
+    /// An iterator visiting all keys in arbitrary order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::PersistentMultiMap;
+    ///
+    /// let map = PersistentMultiMap::new().insert(1, 42).insert(1, 1337).insert(2, 2332);
+    /// let mut keys: Vec<_> = map.keys().collect();
+    /// keys.sort();
+    /// assert_eq!(keys, [&1, &2]);
+    /// ```
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter_all().map(|(k, _)| k)
+    }
+
+    /// An iterator visiting all key-value pairs in arbitrary order. The iterator returns
+    /// a reference to the key and the corresponding key's vector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::PersistentMultiMap;
+    ///
+    /// let map = PersistentMultiMap::new().insert(1, 42).insert(1, 1337).insert(3, 2332);
+    /// let mut pairs: Vec<_> = map.iter_all().collect();
+    /// pairs.sort_by_key(|p| p.0);
+    /// assert_eq!(pairs, [(&1, &[42, 1337][..]), (&3, &[2332][..])]);
+    /// ```
+    pub fn iter_all(&self) -> PersistentIterAll<'_, K, V, N> {
+        PersistentIterAll {
+            stack: vec![self.root.children.iter()],
+            entries: [].iter(),
+            remaining: self.len,
+        }
+    }
+
+    /// Returns a new map with the value added to the key's vector. The map itself
+    /// is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::PersistentMultiMap;
+    ///
+    /// let empty = PersistentMultiMap::new();
+    /// let map = empty.insert("key", 42);
+    /// assert!(empty.is_empty());
+    /// assert_eq!(map.get("key"), Some(&42));
+    /// ```
+    pub fn insert(&self, k: K, v: V) -> PersistentMultiMap<K, V, S, N>
+    where
+        K: Clone,
+        V: Clone,
+        S: Clone,
+    {
+        let hash = self.hash_builder.hash_one(&k);
+        let (root, added) = self.root.insert(hash, 0, k, v);
+        PersistentMultiMap {
+            root: Arc::new(root),
+            len: self.len + usize::from(added),
+            hash_builder: self.hash_builder.clone(),
+        }
+    }
+
+    /// Returns a new map without the key. The map itself is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::PersistentMultiMap;
+    ///
+    /// let map = PersistentMultiMap::new().insert(1, 42).insert(2, 1337);
+    /// let removed = map.remove(&1);
+    /// assert!(map.contains_key(&1));
+    /// assert!(!removed.contains_key(&1));
+    /// assert_eq!(removed.len(), 1);
+    /// ```
+    pub fn remove<Q>(&self, k: &Q) -> PersistentMultiMap<K, V, S, N>
+    where
+        K: Borrow<Q> + Clone,
+        V: Clone,
+        Q: ?Sized + Eq + Hash,
+        S: Clone,
+    {
+        self.modify(k, |_| Some(None))
+    }
+
+    /// Returns a new map without the first occurrence of the value in the key's
+    /// vector. If that leaves the key without values, the key is removed as well.
+    /// The map itself is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::PersistentMultiMap;
+    ///
+    /// let map = PersistentMultiMap::new().insert(1, 42).insert(1, 1337);
+    /// let map = map.remove_value(&1, &42);
+    /// assert_eq!(map.get_slice(&1), Some(&[1337][..]));
+    /// let map = map.remove_value(&1, &1337);
+    /// assert!(!map.contains_key(&1));
+    /// ```
+    pub fn remove_value<Q>(&self, k: &Q, v: &V) -> PersistentMultiMap<K, V, S, N>
+    where
+        K: Borrow<Q> + Clone,
+        V: Clone + PartialEq,
+        Q: ?Sized + Eq + Hash,
+        S: Clone,
+    {
+        self.modify(k, |values| {
+            let index = values.iter().position(|value| value == v)?;
+            let mut values = values.clone();
+            values.remove(index);
+            Some(if values.is_empty() {
+                None
+            } else {
+                Some(values)
+            })
+        })
+    }
+
+    fn modify<Q, F>(&self, k: &Q, f: F) -> PersistentMultiMap<K, V, S, N>
+    where
+        K: Borrow<Q> + Clone,
+        V: Clone,
+        Q: ?Sized + Eq + Hash,
+        S: Clone,
+        F: FnOnce(&SmallVec<[V; N]>) -> Option<Option<SmallVec<[V; N]>>>,
+    {
+        let hash = self.hash_builder.hash_one(k);
+        match self.root.modify(hash, 0, k, f) {
+            Some((root, removed)) => PersistentMultiMap {
+                root: Arc::new(root),
+                len: self.len - usize::from(removed),
+                hash_builder: self.hash_builder.clone(),
+            },
+            None => self.clone(),
+        }
+    }
+}
+
+impl<K, V, S, const N: usize> Clone for PersistentMultiMap<K, V, S, N>
+where
+    S: Clone,
+{
+    fn clone(&self) -> PersistentMultiMap<K, V, S, N> {
+        PersistentMultiMap {
+            root: Arc::clone(&self.root),
+            len: self.len,
+            hash_builder: self.hash_builder.clone(),
+        }
+    }
+}
+
+impl<K, V, S, const N: usize> Default for PersistentMultiMap<K, V, S, N>
+where
+    K: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    fn default() -> PersistentMultiMap<K, V, S, N> {
+        PersistentMultiMap::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S, const N: usize> Debug for PersistentMultiMap<K, V, S, N>
+where
+    K: Eq + Hash + Debug,
+    V: Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter_all()).finish()
+    }
+}
+
+impl<K, V, S, const N: usize> FromIterator<(K, V)> for PersistentMultiMap<K, V, S, N>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    S: BuildHasher + Clone + Default,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iterable: T) -> PersistentMultiMap<K, V, S, N> {
+        iterable
+            .into_iter()
+            .fold(PersistentMultiMap::default(), |map, (k, v)| {
+                map.insert(k, v)
+            })
+    }
+}
+
+/// An iterator over the keys and values of a PersistentMultiMap.
+pub struct PersistentIterAll<'a, K: 'a, V: 'a, const N: usize> {
+    stack: Vec<slice::Iter<'a, Child<K, V, N>>>,
+    entries: slice::Iter<'a, (K, SmallVec<[V; N]>)>,
+    remaining: usize,
+}
+
+impl<'a, K, V, const N: usize> Iterator for PersistentIterAll<'a, K, V, N> {
+    type Item = (&'a K, &'a [V]);
+
+    fn next(&mut self) -> Option<(&'a K, &'a [V])> {
+        loop {
+            if let Some((k, values)) = self.entries.next() {
+                self.remaining -= 1;
+                return Some((k, values.as_slice()));
+            }
+            match self.stack.last_mut()?.next() {
+                Some(Child::Leaf(leaf)) => self.entries = leaf.entries.iter(),
+                Some(Child::Branch(branch)) => self.stack.push(branch.children.iter()),
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K, V, const N: usize> ExactSizeIterator for PersistentIterAll<'a, K, V, N> {}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::{BuildHasherDefault, Hasher};
+
+    use super::*;
+    use test_util::*;
+
+    /// Hashes every key to the same value, so all keys collide.
+    #[derive(Default)]
+    struct ConstantHasher;
+
+    impl Hasher for ConstantHasher {
+        fn finish(&self) -> u64 {
+            0x5555
+        }
+
+        fn write(&mut self, _: &[u8]) {}
+    }
+
+    /// Uses the key itself as hash, so neighbouring keys share long prefixes.
+    #[derive(Default)]
+    struct IdentityHasher(u64);
+
+    impl Hasher for IdentityHasher {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for &b in bytes {
+                self.0 = (self.0 << 8) | u64::from(b);
+            }
+        }
+
+        fn write_u64(&mut self, i: u64) {
+            self.0 = i;
+        }
+    }
+
+    #[test]
+    fn versions_are_independent() {
+        let v1 = PersistentMultiMap::<_, _, TestState>::default()
+            .insert(1, 42)
+            .insert(2, 2332);
+        let v2 = v1.insert(1, 1337);
+        let v3 = v2.remove(&2);
+
+        assert_eq!(v1.get_slice(&1), Some(&[42][..]));
+        assert_eq!(v2.get_slice(&1), Some(&[42, 1337][..]));
+        assert_eq!(v2.len(), 2);
+        assert_eq!(v3.len(), 1);
+        assert!(v1.contains_key(&2));
+        assert!(!v3.contains_key(&2));
+    }
+
+    #[test]
+    fn remove_missing_shares_root() {
+        let map = PersistentMultiMap::<_, _, TestState>::default().insert(1, 42);
+        let same = map.remove(&2).remove_value(&1, &1337);
+        assert!(Arc::ptr_eq(&map.root, &same.root));
+    }
+
+    #[test]
+    fn colliding_keys() {
+        let map: PersistentMultiMap<u32, u32, BuildHasherDefault<ConstantHasher>> =
+            (0..10).map(|i| (i % 5, i)).collect();
+
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.get_slice(&3), Some(&[3, 8][..]));
+
+        let map = map.remove(&3).remove_value(&4, &4);
+        assert_eq!(map.len(), 4);
+        assert!(!map.contains_key(&3));
+        assert_eq!(map.get_slice(&4), Some(&[9][..]));
+        assert_eq!(map.iter_all().count(), 4);
+    }
+
+    #[test]
+    fn deep_paths_collapse() {
+        let keys = [0u64, 1 << 60, 1 << 40, 1 << 40 | 1];
+        let mut map: PersistentMultiMap<u64, u64, BuildHasherDefault<IdentityHasher>> =
+            keys.iter().map(|&k| (k, k)).collect();
+
+        for &k in &keys {
+            assert_eq!(map.get(&k), Some(&k));
+        }
+        for &k in &keys[1..] {
+            map = map.remove(&k);
+        }
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.root.children.len(), 1);
+        assert!(matches!(map.root.children[0], Child::Leaf(_)));
+    }
+
+    #[test]
+    fn matches_multimap() {
+        let mut expected = TestMap::default();
+        let mut map = PersistentMultiMap::<_, _, TestState>::default();
+        for i in 0..2000u32 {
+            let k = i.wrapping_mul(2654435761) % 300;
+            if i % 3 == 0 {
+                expected.remove(&k);
+                map = map.remove(&k);
+            } else {
+                expected.insert(k, i);
+                map = map.insert(k, i);
+            }
+        }
+
+        assert_eq!(map.len(), expected.len());
+        assert_eq!(map.iter_all().len(), expected.len());
+        for (k, values) in map.iter_all() {
+            assert_eq!(expected.get_slice(k), Some(values));
+        }
+    }
+}
@@ -0,0 +1,205 @@
+// Copyright (c) 2016 multimap developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Parsing and encoding of URL query strings and form-urlencoded bodies.
+//!
+//! Both follow the `application/x-www-form-urlencoded` rules of the WHATWG URL
+//! standard: pairs are separated by `&`, a `+` stands for a space, and bytes
+//! may be percent-encoded. Parsing never fails; invalid percent sequences are
+//! kept as they are and invalid UTF-8 is replaced with U+FFFD.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::hash::BuildHasher;
+
+use MultiMap;
+
+impl<S> MultiMap<String, String, S>
+where
+    S: BuildHasher + Default,
+{
+    /// Parses a URL query string into a MultiMap. A leading `?` is ignored.
+    ///
+    /// Every `name=value` pair is inserted in the order in which it appears,
+    /// so the values of a key keep their order. A pair without `=` is
+    /// inserted with an empty value, and empty pairs are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let queries: MultiMap<String, String> =
+    ///     MultiMap::from_query_str("?urls=http%3A%2F%2Frust-lang.org&id=42&urls=a+b");
+    /// assert_eq!(queries.get_slice("urls").unwrap(), ["http://rust-lang.org", "a b"]);
+    /// assert_eq!(queries.get("id").unwrap(), "42");
+    /// ```
+    pub fn from_query_str(query: &str) -> Self {
+        let query = query.strip_prefix('?').unwrap_or(query);
+        Self::parse_form_urlencoded(query.as_bytes())
+    }
+
+    /// Parses an `application/x-www-form-urlencoded` body into a MultiMap.
+    ///
+    /// This behaves like `from_query_str`, except that a leading `?` is not
+    /// treated specially.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let form: MultiMap<String, String> =
+    ///     MultiMap::parse_form_urlencoded(b"tag=rust&tag=caf%C3%A9&empty");
+    /// assert_eq!(form.get_slice("tag").unwrap(), ["rust", "café"]);
+    /// assert_eq!(form.get("empty").unwrap(), "");
+    /// ```
+    pub fn parse_form_urlencoded(input: &[u8]) -> Self {
+        let mut map = MultiMap::default();
+        for pair in input.split(|&b| b == b'&') {
+            if pair.is_empty() {
+                continue;
+            }
+            let (name, value) = match pair.iter().position(|&b| b == b'=') {
+                Some(i) => (&pair[..i], &pair[i + 1..]),
+                None => (pair, &[][..]),
+            };
+            map.insert(decode(name), decode(value));
+        }
+        map
+    }
+}
+
+impl<S> MultiMap<String, String, S>
+where
+    S: BuildHasher,
+{
+    /// Encodes the map as a URL query string, without a leading `?`.
+    ///
+    /// Keys are written in arbitrary order, but the values of each key are
+    /// written in their order in the map, so parsing the result with
+    /// `from_query_str` gives back an equal map. A key without values is
+    /// omitted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut queries = MultiMap::new();
+    /// queries.insert("q".to_string(), "rust lang".to_string());
+    /// queries.insert("q".to_string(), "a&b".to_string());
+    /// assert_eq!(queries.to_query_string(), "q=rust+lang&q=a%26b");
+    /// ```
+    pub fn to_query_string(&self) -> String {
+        let mut out = String::new();
+        for (key, values) in self.iter_all() {
+            for value in values {
+                if !out.is_empty() {
+                    out.push('&');
+                }
+                encode_into(key, &mut out);
+                out.push('=');
+                encode_into(value, &mut out);
+            }
+        }
+        out
+    }
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn decode(input: &[u8]) -> String {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        match input[i] {
+            b'+' => bytes.push(b' '),
+            b'%' => match (
+                input.get(i + 1).cloned().and_then(hex_value),
+                input.get(i + 2).cloned().and_then(hex_value),
+            ) {
+                (Some(hi), Some(lo)) => {
+                    bytes.push(hi << 4 | lo);
+                    i += 2;
+                }
+                _ => bytes.push(b'%'),
+            },
+            b => bytes.push(b),
+        }
+        i += 1;
+    }
+
+    match String::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+    }
+}
+
+fn encode_into(input: &str, out: &mut String) {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+    for &b in input.as_bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'*' | b'-' | b'.' | b'_' => {
+                out.push(b as char)
+            }
+            b' ' => out.push('+'),
+            _ => {
+                out.push('%');
+                out.push(HEX[(b >> 4) as usize] as char);
+                out.push(HEX[(b & 0xf) as usize] as char);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::*;
+
+    #[test]
+    fn parse_query() {
+        let map: TestMap<String, String> =
+            MultiMap::from_query_str("?a=1&b=%41%4a&a=%zz&&c&a=x%2By+z&%=%");
+        assert_eq!(map.get_slice("a").unwrap(), ["1", "%zz", "x+y z"]);
+        assert_eq!(map.get_slice("b").unwrap(), ["AJ"]);
+        assert_eq!(map.get_slice("c").unwrap(), [""]);
+        assert_eq!(map.get_slice("%").unwrap(), ["%"]);
+        assert_eq!(map.len(), 4);
+    }
+
+    #[test]
+    fn parse_invalid_utf8() {
+        let map: TestMap<String, String> = MultiMap::parse_form_urlencoded(b"k=%FF%41");
+        assert_eq!(map.get("k").unwrap(), "\u{FFFD}A");
+    }
+
+    #[test]
+    fn query_string_roundtrip() {
+        let mut map: TestMap<String, String> = TestMap::default();
+        map.insert("k y".to_string(), "a=b&c".to_string());
+        map.insert("k y".to_string(), "100%".to_string());
+        map.insert("k y".to_string(), "".to_string());
+        map.insert("ü".to_string(), "+/?#".to_string());
+        map.insert_many("none".to_string(), vec![]);
+
+        let query = map.to_query_string();
+        let parsed: TestMap<String, String> = MultiMap::from_query_str(&query);
+        map.remove("none");
+        assert_eq!(parsed, map);
+    }
+}
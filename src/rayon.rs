@@ -0,0 +1,214 @@
+// Copyright (c) 2016 multimap developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Rayon parallel iterator implementations for MultiMap
+
+extern crate rayon;
+
+use std::hash::{BuildHasher, Hash};
+
+#[cfg(not(feature = "hashbrown"))]
+use self::rayon::collections::hash_map::IntoIter as HashMapIntoParIter;
+use self::rayon::iter::{
+    FromParallelIterator, IntoParallelIterator, IntoParallelRefIterator,
+    IntoParallelRefMutIterator, Map, ParallelExtend, ParallelIterator,
+};
+#[cfg(feature = "hashbrown")]
+use hashbrown::hash_map::rayon::IntoParIter as HashMapIntoParIter;
+use smallvec::SmallVec;
+
+use MultiMap;
+
+impl<K, V, S> MultiMap<K, V, S>
+where
+    K: Eq + Hash + Sync,
+    V: Sync,
+    S: BuildHasher + Sync,
+{
+    /// A parallel iterator visiting all key-value pairs in arbitrary order. The
+    /// iterator returns a reference to the key and the corresponding key's vector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rayon;
+    /// use multimap::MultiMap;
+    /// use rayon::prelude::*;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// map.insert(3, 2332);
+    ///
+    /// let total: usize = map.par_iter_all().map(|(_, values)| values.len()).sum();
+    /// assert_eq!(total, 3);
+    /// ```
+    pub fn par_iter_all(&self) -> impl ParallelIterator<Item = (&K, &[V])> {
+        self.inner.par_iter().map(|(k, v)| (k, &v[..]))
+    }
+
+    /// A parallel iterator visiting all keys in arbitrary order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rayon;
+    /// use multimap::MultiMap;
+    /// use rayon::prelude::*;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// map.insert(3, 2332);
+    ///
+    /// let sum: usize = map.par_keys().sum();
+    /// assert_eq!(sum, 4);
+    /// ```
+    pub fn par_keys(&self) -> impl ParallelIterator<Item = &K> {
+        self.inner.par_iter().map(|(k, _)| k)
+    }
+}
+
+impl<K, V, S> MultiMap<K, V, S>
+where
+    K: Eq + Hash + Sync,
+    V: Send,
+    S: BuildHasher,
+{
+    /// A parallel iterator visiting all key-value pairs in arbitrary order. The
+    /// iterator returns a reference to the key and a mutable reference to the
+    /// corresponding key's vector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rayon;
+    /// use multimap::MultiMap;
+    /// use rayon::prelude::*;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    ///
+    /// map.par_iter_all_mut().for_each(|(_, values)| values.reverse());
+    /// assert_eq!(map.get_slice(&1), Some(&[1337, 42][..]));
+    /// ```
+    pub fn par_iter_all_mut(&mut self) -> impl ParallelIterator<Item = (&K, &mut [V])> {
+        self.inner.par_iter_mut().map(|(k, v)| (k, &mut v[..]))
+    }
+}
+
+type IntoParIterItem<K, V> = (K, Vec<V>);
+
+impl<K, V, S> IntoParallelIterator for MultiMap<K, V, S>
+where
+    K: Eq + Hash + Send,
+    V: Send,
+    S: BuildHasher,
+{
+    type Item = IntoParIterItem<K, V>;
+    type Iter = Map<
+        HashMapIntoParIter<K, SmallVec<[V; 1]>>,
+        fn((K, SmallVec<[V; 1]>)) -> IntoParIterItem<K, V>,
+    >;
+
+    fn into_par_iter(self) -> Self::Iter {
+        self.inner.into_par_iter().map(|(k, v)| (k, v.into_vec()))
+    }
+}
+
+/// Collects the pairs into one map per rayon job, which are merged afterwards in
+/// the order of the pairs.
+fn collect_par_iter<I, F, K, V, S>(par_iter: I, new_map: F) -> MultiMap<K, V, S>
+where
+    I: IntoParallelIterator<Item = (K, V)>,
+    F: Fn() -> MultiMap<K, V, S> + Sync + Send,
+    K: Eq + Hash + Send,
+    V: Send,
+    S: BuildHasher + Send,
+{
+    par_iter
+        .into_par_iter()
+        .fold(&new_map, |mut map, (k, v)| {
+            map.insert(k, v);
+            map
+        })
+        .reduce(&new_map, |mut map, other| {
+            map.extend(other.inner.into_iter().map(|(k, v)| (k, v.into_vec())));
+            map
+        })
+}
+
+impl<K, V, S> FromParallelIterator<(K, V)> for MultiMap<K, V, S>
+where
+    K: Eq + Hash + Send,
+    V: Send,
+    S: BuildHasher + Default + Send,
+{
+    fn from_par_iter<I>(par_iter: I) -> MultiMap<K, V, S>
+    where
+        I: IntoParallelIterator<Item = (K, V)>,
+    {
+        collect_par_iter(par_iter, || MultiMap::with_hasher(S::default()))
+    }
+}
+
+impl<K, V, S> ParallelExtend<(K, V)> for MultiMap<K, V, S>
+where
+    K: Eq + Hash + Send,
+    V: Send,
+    S: BuildHasher + Clone + Send + Sync,
+{
+    fn par_extend<I>(&mut self, par_iter: I)
+    where
+        I: IntoParallelIterator<Item = (K, V)>,
+    {
+        let hash_builder = self.inner.hasher().clone();
+        let other = collect_par_iter(par_iter, || MultiMap::with_hasher(hash_builder.clone()));
+        self.extend(other.inner.into_iter().map(|(k, v)| (k, v.into_vec())));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_par_iter_keeps_order() {
+        let map: MultiMap<usize, usize> = (0..10_000).into_par_iter().map(|i| (i % 7, i)).collect();
+
+        assert_eq!(map.len(), 7);
+        for (k, values) in map.iter_all() {
+            assert!(values.windows(2).all(|w| w[0] < w[1]));
+            assert!(values.iter().all(|v| v % 7 == *k));
+        }
+    }
+
+    #[test]
+    fn par_extend() {
+        let mut map = MultiMap::new();
+        map.insert(0, usize::MAX);
+        map.par_extend((0..100).into_par_iter().map(|i| (i % 2, i)));
+
+        assert_eq!(map.get_slice(&0).map(|v| v.len()), Some(51));
+        assert_eq!(map.get(&0), Some(&usize::MAX));
+        assert_eq!(map.get_slice(&1).map(|v| v.len()), Some(50));
+    }
+
+    #[test]
+    fn into_par_iter() {
+        let mut map = MultiMap::new();
+        map.insert(1, 42);
+        map.insert(1, 1337);
+        map.insert(2, 2332);
+
+        let mut pairs: Vec<_> = map.into_par_iter().collect();
+        pairs.sort();
+        assert_eq!(pairs, vec![(1, vec![42, 1337]), (2, vec![2332])]);
+    }
+}
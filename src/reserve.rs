@@ -0,0 +1,269 @@
+// Copyright (c) 2016 multimap developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Shrinking and reserving the hash table and the per-key value lists, and
+//! fallible insertion.
+
+use core::alloc::Layout;
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+
+#[cfg(feature = "hashbrown")]
+use hashbrown as hash_map_reserve;
+use smallvec::{CollectionAllocErr, SmallVec};
+#[cfg(not(feature = "hashbrown"))]
+use std::collections as hash_map_reserve;
+
+use MultiMap;
+
+/// The error returned when reserving memory for a MultiMap fails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TryReserveError {
+    /// The hash table could not grow to hold more keys.
+    Keys(TableReserveError),
+
+    /// The capacity needed for the values of a key overflows.
+    CapacityOverflow,
+
+    /// The allocator failed to allocate the values of a key.
+    AllocError {
+        /// The layout of the failed allocation.
+        layout: Layout,
+    },
+}
+
+/// The error of the hash table when it could not grow. With the `std` hash
+/// table it is the source of `TryReserveError::Keys`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableReserveError {
+    inner: hash_map_reserve::TryReserveError,
+}
+
+impl fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TryReserveError::Keys(_) => f.write_str("failed to grow the hash table"),
+            TryReserveError::CapacityOverflow => f.write_str("value capacity overflow"),
+            TryReserveError::AllocError { layout } => {
+                write!(f, "failed to allocate {} bytes for values", layout.size())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TryReserveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(not(feature = "hashbrown"))]
+            TryReserveError::Keys(e) => Some(&e.inner),
+            _ => None,
+        }
+    }
+}
+
+impl From<CollectionAllocErr> for TryReserveError {
+    fn from(e: CollectionAllocErr) -> TryReserveError {
+        match e {
+            CollectionAllocErr::CapacityOverflow => TryReserveError::CapacityOverflow,
+            CollectionAllocErr::AllocErr { layout } => TryReserveError::AllocError { layout },
+        }
+    }
+}
+
+impl<K, V, S, const N: usize> MultiMap<K, V, S, N>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Shrinks the hash table as much as possible, and the values of every
+    /// key. Values which fit in the inline buffer of their key after
+    /// shrinking are moved back into it, freeing their heap buffer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::{MultiMap, MultiMapValue};
+    ///
+    /// let mut map = MultiMap::with_capacity(100);
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// map.get_all_mut(&1).unwrap().pop();
+    /// assert_eq!(map.stats().spilled_keys, 1);
+    ///
+    /// map.shrink_to_fit();
+    /// assert!(map.capacity() < 100);
+    /// assert_eq!(map.stats().spilled_keys, 0);
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.inner.shrink_to_fit();
+        self.shrink_values_to_fit();
+    }
+
+    /// Shrinks the values of every key as much as possible, moving them back
+    /// into the inline buffer of their key where they fit. The hash table is
+    /// left as is.
+    pub fn shrink_values_to_fit(&mut self) {
+        for values in self.inner.values_mut() {
+            values.shrink_to_fit();
+        }
+    }
+
+    /// Reserves capacity for at least `additional` more values of the key.
+    /// Returns false, and reserves nothing, if the key is not in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert("a", 1);
+    /// assert!(map.reserve_values("a", 10));
+    /// assert!(!map.reserve_values("b", 10));
+    /// assert!(map.stats().heap_bytes >= 11 * 4);
+    /// ```
+    pub fn reserve_values<Q>(&mut self, k: &Q, additional: usize) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        match self.inner.get_mut(k) {
+            Some(values) => {
+                values.reserve(additional);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Tries to reserve capacity for at least `additional` more keys, and
+    /// returns an error instead of aborting if the allocation fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::{MultiMap, TryReserveError};
+    ///
+    /// let mut map: MultiMap<u32, u32> = MultiMap::new();
+    /// map.try_reserve(10).unwrap();
+    /// assert!(map.capacity() >= 10);
+    /// match map.try_reserve(usize::MAX) {
+    ///     Err(TryReserveError::Keys(_)) => {}
+    ///     other => panic!("unexpected {:?}", other),
+    /// }
+    /// ```
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.inner
+            .try_reserve(additional)
+            .map_err(|inner| TryReserveError::Keys(TableReserveError { inner }))
+    }
+
+    /// Inserts a key-value pair into the map like `insert`, but returns an
+    /// error instead of aborting if the hash table or the values of the key
+    /// cannot grow. The map is unchanged on error, and the value is returned
+    /// with the error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.try_insert(1, 42).unwrap();
+    /// map.try_insert(1, 1337).unwrap();
+    /// assert_eq!(map.get_slice(&1), Some(&[42, 1337][..]));
+    /// ```
+    pub fn try_insert(&mut self, k: K, v: V) -> Result<(), (TryReserveError, V)> {
+        if let Some(values) = self.inner.get_mut(&k) {
+            if let Err(e) = values.try_reserve(1) {
+                return Err((e.into(), v));
+            }
+            values.push(v);
+            return Ok(());
+        }
+
+        // The table only needs to grow for a new key. Reserving first makes
+        // sure `insert` does not grow it itself.
+        if let Err(e) = self.try_reserve(1) {
+            return Err((e, v));
+        }
+        let mut values = SmallVec::new();
+        if let Err(e) = values.try_reserve(1) {
+            return Err((e.into(), v));
+        }
+        values.push(v);
+        self.inner.insert(k, values);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::*;
+
+    use hash_map::HashMap;
+
+    #[test]
+    fn shrink_moves_values_inline() {
+        let mut map: MultiMap<u8, u64, TestState, 2> = MultiMap {
+            inner: HashMap::default(),
+        };
+        map.inner.insert(0, SmallVec::from_slice(&[1, 2, 3, 4, 5]));
+        map.inner.insert(1, SmallVec::from_slice(&[1, 2, 3]));
+        map.inner.get_mut(&0).unwrap().truncate(3);
+        map.inner.get_mut(&1).unwrap().truncate(2);
+        assert_eq!(map.stats().spilled_keys, 2);
+
+        map.shrink_values_to_fit();
+        let stats = map.stats();
+        assert_eq!(stats.spilled_keys, 1);
+        assert_eq!(stats.heap_bytes, 3 * 8);
+        assert_eq!(stats.wasted_capacity, 0);
+        assert_eq!(&map.inner[&0][..], &[1, 2, 3]);
+        assert_eq!(&map.inner[&1][..], &[1, 2]);
+    }
+
+    #[test]
+    fn try_insert_existing_key_keeps_table() {
+        let mut map: TestMap<u32, u32> = TestMap::default();
+        map.try_insert(0, 0).unwrap();
+        let capacity = map.capacity();
+        for k in 1..capacity as u32 {
+            map.try_insert(k, k).unwrap();
+        }
+        assert_eq!(map.len(), capacity);
+
+        map.try_insert(0, 1).unwrap();
+        assert_eq!(map.capacity(), capacity);
+        assert_eq!(map.get_slice(&0), Some(&[0, 1][..]));
+
+        map.try_insert(capacity as u32, 0).unwrap();
+        assert!(map.capacity() > capacity);
+    }
+
+    #[test]
+    fn try_reserve_errors() {
+        let mut map: TestMap<u8, u8> = TestMap::default();
+        map.try_insert(1, 1).unwrap();
+        map.try_insert(1, 2).unwrap();
+        let capacity = map.capacity();
+
+        let e = map.try_reserve(usize::MAX).unwrap_err();
+        assert!(matches!(e, TryReserveError::Keys(_)));
+        assert_eq!(e.to_string(), "failed to grow the hash table");
+        #[cfg(not(feature = "hashbrown"))]
+        assert!(std::error::Error::source(&e).is_some());
+
+        assert_eq!(map.capacity(), capacity);
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get_slice(&1), Some(&[1, 2][..]));
+    }
+}
@@ -0,0 +1,349 @@
+// Copyright (c) 2016 multimap developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! A MultiMap which keeps at most a fixed number of values per key.
+
+use core::borrow::Borrow;
+use core::fmt::{self, Debug};
+use core::hash::{BuildHasher, Hash};
+use core::mem;
+#[cfg(feature = "std")]
+use std::collections::hash_map::RandomState;
+
+use hash_map::HashMap;
+use smallvec::SmallVec;
+
+/// Which value a RingMultiMap gives up when a value is inserted for a key
+/// which already holds the maximum number of values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Evict the oldest value of the key and append the new value.
+    DropOldest,
+
+    /// Evict the newest value of the key and append the new value in its
+    /// place.
+    DropNewest,
+
+    /// Keep the values of the key and reject the new value.
+    Reject,
+}
+
+/// The values of a key, as a ring buffer whose oldest value is at `head`.
+#[derive(Clone)]
+struct Ring<V, const N: usize> {
+    values: SmallVec<[V; N]>,
+    head: usize,
+}
+
+impl<V, const N: usize> Ring<V, N> {
+    /// Returns the values from oldest to newest, as two slices.
+    fn as_slices(&self) -> (&[V], &[V]) {
+        let (newer, older) = self.values.split_at(self.head);
+        (older, newer)
+    }
+
+    /// Returns the values from oldest to newest.
+    fn into_ordered(mut self) -> SmallVec<[V; N]> {
+        self.values.rotate_left(self.head);
+        self.values
+    }
+}
+
+impl<V: Debug, const N: usize> Debug for Ring<V, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (older, newer) = self.as_slices();
+        f.debug_list().entries(older.iter().chain(newer)).finish()
+    }
+}
+
+/// A map which allows storing multiple values per key, and keeps at most
+/// `limit` values per key. When a key is full, `insert` evicts a value
+/// according to the map's `EvictionPolicy` and returns it.
+///
+/// The values of each key form a ring buffer in a `SmallVec<[V; N]>`, so with
+/// a `limit` of at most `N` the values never leave the inline buffer and
+/// inserts never allocate beyond the hash table. Once a key is full, every
+/// policy evicts in constant time by overwriting a slot in place. Because the
+/// oldest value may sit anywhere in the buffer, the values of a key are read
+/// as two slices, like those of a `VecDeque`.
+///
+/// # Examples
+///
+/// ```
+/// use multimap::{EvictionPolicy, RingMultiMap};
+/// use std::collections::hash_map::RandomState;
+///
+/// let mut events: RingMultiMap<&str, u32, RandomState, 3> =
+///     RingMultiMap::new(3, EvictionPolicy::DropOldest);
+/// for event in 1..=5 {
+///     events.insert("device", event);
+/// }
+/// let (older, newer) = events.get_slices("device").unwrap();
+/// assert_eq!([older, newer].concat(), [3, 4, 5]);
+/// ```
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct RingMultiMap<K, V, S = RandomState, const N: usize = 1> {
+    map: HashMap<K, Ring<V, N>, S>,
+    limit: usize,
+    policy: EvictionPolicy,
+}
+
+/// A map which allows storing multiple values per key, and keeps at most
+/// `limit` values per key.
+#[cfg(not(feature = "std"))]
+#[derive(Clone)]
+pub struct RingMultiMap<K, V, S, const N: usize = 1> {
+    map: HashMap<K, Ring<V, N>, S>,
+    limit: usize,
+    policy: EvictionPolicy,
+}
+
+#[cfg(feature = "std")]
+impl<K, V, const N: usize> RingMultiMap<K, V, RandomState, N>
+where
+    K: Eq + Hash,
+{
+    /// Creates an empty map which keeps at most `limit` values per key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is 0.
+    pub fn new(limit: usize, policy: EvictionPolicy) -> RingMultiMap<K, V, RandomState, N> {
+        RingMultiMap::with_hasher(limit, policy, RandomState::new())
+    }
+}
+
+impl<K, V, S, const N: usize> RingMultiMap<K, V, S, N>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Creates an empty map which keeps at most `limit` values per key and
+    /// will use the given hash builder to hash keys.
+    ///
+    /// A `limit` greater than `N` is allowed, but keys holding more than `N`
+    /// values then spill to the heap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is 0.
+    pub fn with_hasher(
+        limit: usize,
+        policy: EvictionPolicy,
+        hash_builder: S,
+    ) -> RingMultiMap<K, V, S, N> {
+        assert!(limit > 0, "limit must be at least 1");
+        RingMultiMap {
+            map: HashMap::with_hasher(hash_builder),
+            limit,
+            policy,
+        }
+    }
+
+    /// Returns the maximum number of values per key.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Returns the eviction policy.
+    pub fn policy(&self) -> EvictionPolicy {
+        self.policy
+    }
+
+    /// Inserts a key-value pair into the map. If the key already holds
+    /// `limit` values, a value is evicted according to the policy and
+    /// returned: the oldest or newest stored value, or the new value itself
+    /// when it is rejected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::{EvictionPolicy, RingMultiMap};
+    /// use std::collections::hash_map::RandomState;
+    ///
+    /// let mut map: RingMultiMap<_, _, RandomState, 2> =
+    ///     RingMultiMap::new(2, EvictionPolicy::DropNewest);
+    /// assert_eq!(map.insert(1, 'a'), None);
+    /// assert_eq!(map.insert(1, 'b'), None);
+    /// assert_eq!(map.insert(1, 'c'), Some('b'));
+    /// assert_eq!(map.get_slices(&1), Some((&['a', 'c'][..], &[][..])));
+    /// ```
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        let ring = self.map.entry(k).or_insert_with(|| Ring {
+            values: SmallVec::new(),
+            head: 0,
+        });
+        if ring.values.len() < self.limit {
+            ring.values.push(v);
+            return None;
+        }
+
+        match self.policy {
+            EvictionPolicy::DropOldest => {
+                let evicted = mem::replace(&mut ring.values[ring.head], v);
+                ring.head = (ring.head + 1) % self.limit;
+                Some(evicted)
+            }
+            EvictionPolicy::DropNewest => {
+                let newest = (ring.head + self.limit - 1) % self.limit;
+                Some(mem::replace(&mut ring.values[newest], v))
+            }
+            EvictionPolicy::Reject => Some(v),
+        }
+    }
+
+    /// Returns the number of keys in the map.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns true if the map contains no keys.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns true if the map contains the key.
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.map.contains_key(k)
+    }
+
+    /// Returns a reference to the oldest value of the key.
+    pub fn get<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let ring = self.map.get(k)?;
+        ring.values.get(ring.head)
+    }
+
+    /// Returns the values of the key from oldest to newest, as two slices.
+    /// The first slice holds the oldest values, and the second slice, which
+    /// is empty until the key has evicted a value, the newest ones.
+    pub fn get_slices<Q>(&self, k: &Q) -> Option<(&[V], &[V])>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.map.get(k).map(Ring::as_slices)
+    }
+
+    /// Returns an iterator over the values of the key, from oldest to newest.
+    pub fn get_iter<Q>(&self, k: &Q) -> Option<impl Iterator<Item = &V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let (older, newer) = self.get_slices(k)?;
+        Some(older.iter().chain(newer))
+    }
+
+    /// Removes a key from the map, returning its values from oldest to newest
+    /// if the key was in the map.
+    pub fn remove<Q>(&mut self, k: &Q) -> Option<impl Iterator<Item = V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.map
+            .remove(k)
+            .map(|ring| ring.into_ordered().into_iter())
+    }
+
+    /// Removes all keys and values.
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// An iterator visiting all keys in arbitrary order, with the values of
+    /// each key from oldest to newest, as two slices.
+    pub fn iter_all(&self) -> impl Iterator<Item = (&K, (&[V], &[V]))> {
+        self.map.iter().map(|(k, ring)| (k, ring.as_slices()))
+    }
+}
+
+impl<K, V, S, const N: usize> Debug for RingMultiMap<K, V, S, N>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.map.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::*;
+
+    fn fill(policy: EvictionPolicy) -> (RingMultiMap<u8, u32, TestState, 4>, Vec<u32>) {
+        let mut map = RingMultiMap::with_hasher(3, policy, TestState::default());
+        let evicted = (1..=7).filter_map(|i| map.insert(0, i)).collect();
+        (map, evicted)
+    }
+
+    fn values(map: &RingMultiMap<u8, u32, TestState, 4>) -> Vec<u32> {
+        map.get_iter(&0).unwrap().cloned().collect()
+    }
+
+    #[test]
+    fn policies() {
+        let (map, evicted) = fill(EvictionPolicy::DropOldest);
+        assert_eq!(values(&map), [5, 6, 7]);
+        assert_eq!(map.get_slices(&0), Some((&[5, 6][..], &[7][..])));
+        assert_eq!(map.get(&0), Some(&5));
+        assert_eq!(evicted, [1, 2, 3, 4]);
+
+        let (map, evicted) = fill(EvictionPolicy::DropNewest);
+        assert_eq!(values(&map), [1, 2, 7]);
+        assert_eq!(evicted, [3, 4, 5, 6]);
+
+        let (map, evicted) = fill(EvictionPolicy::Reject);
+        assert_eq!(values(&map), [1, 2, 3]);
+        assert_eq!(evicted, [4, 5, 6, 7]);
+        assert!(map.map.values().all(|ring| !ring.values.spilled()));
+    }
+
+    #[test]
+    fn drop_oldest_wraps_in_place() {
+        let (mut map, _) = fill(EvictionPolicy::DropOldest);
+        assert_eq!(map.insert(0, 8), Some(5));
+        assert_eq!(map.insert(0, 9), Some(6));
+        assert_eq!(map.get_slices(&0), Some((&[7, 8, 9][..], &[][..])));
+        assert_eq!(format!("{:?}", map), "{0: [7, 8, 9]}");
+
+        assert_eq!(map.insert(0, 10), Some(7));
+        assert_eq!(map.remove(&0).unwrap().collect::<Vec<_>>(), [8, 9, 10]);
+    }
+
+    #[test]
+    fn keys_are_independent() {
+        let mut map: RingMultiMap<u8, u8, TestState> =
+            RingMultiMap::with_hasher(1, EvictionPolicy::DropOldest, TestState::default());
+        assert_eq!(map.insert(1, 1), None);
+        assert_eq!(map.insert(2, 2), None);
+        assert_eq!(map.insert(1, 3), Some(1));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&1), Some(&3));
+        assert_eq!(map.remove(&2).unwrap().collect::<Vec<_>>(), [2]);
+        assert!(!map.contains_key(&2));
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_limit() {
+        let _: RingMultiMap<u8, u8, TestState> =
+            RingMultiMap::with_hasher(0, EvictionPolicy::Reject, TestState::default());
+    }
+}
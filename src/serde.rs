@@ -10,13 +10,16 @@
 
 extern crate serde;
 
-use std::fmt;
-use std::hash::{BuildHasher, Hash};
-use std::marker::PhantomData;
+use core::cmp;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
 
-use self::serde::de::{MapAccess, Visitor};
+use self::serde::de::{DeserializeSeed, Error, MapAccess, SeqAccess, Visitor};
 use self::serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use smallvec::SmallVec;
+
 use MultiMap;
 
 impl<K, V, BS> Serialize for MultiMap<K, V, BS>
@@ -29,7 +32,7 @@ where
     where
         S: Serializer,
     {
-        self.inner.serialize(serializer)
+        serializer.collect_map(self.iter_all())
     }
 }
 
@@ -39,12 +42,21 @@ where
 {
     fn new() -> Self {
         MultiMapVisitor {
+            strict: false,
+            marker: PhantomData,
+        }
+    }
+
+    fn strict() -> Self {
+        MultiMapVisitor {
+            strict: true,
             marker: PhantomData,
         }
     }
 }
 
 struct MultiMapVisitor<K, V, S> {
+    strict: bool,
     marker: PhantomData<MultiMap<K, V, S>>,
 }
 
@@ -57,24 +69,83 @@ where
     type Value = MultiMap<K, V, S>;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("expected a map")
+        formatter.write_str("a map of sequences of values")
     }
 
     fn visit_map<M>(self, mut visitor: M) -> Result<Self::Value, M::Error>
     where
         M: MapAccess<'a>,
     {
-        let mut values =
-            MultiMap::with_capacity_and_hasher(visitor.size_hint().unwrap_or(0), S::default());
+        let capacity = cmp::min(visitor.size_hint().unwrap_or(0), 4096);
+        let mut values = MultiMap::with_capacity_and_hasher(capacity, S::default());
 
-        while let Some((key, value)) = visitor.next_entry()? {
-            values.inner.insert(key, value);
+        while let Some(key) = visitor.next_key::<K>()? {
+            if !self.strict {
+                let list = values.inner.entry(key).or_insert_with(SmallVec::new);
+                visitor.next_value_seed(ValuesSeed { values: list })?;
+                continue;
+            }
+
+            if values.inner.contains_key(&key) {
+                return Err(M::Error::custom("duplicate key in multimap"));
+            }
+            let mut list = SmallVec::new();
+            visitor.next_value_seed(ValuesSeed { values: &mut list })?;
+            if list.is_empty() {
+                return Err(M::Error::invalid_length(
+                    0,
+                    &"a non-empty sequence of values",
+                ));
+            }
+            values.inner.insert(key, list);
         }
 
         Ok(values)
     }
 }
 
+/// Appends a sequence of values to `values`, without an intermediate allocation.
+struct ValuesSeed<'b, V: 'b> {
+    values: &'b mut SmallVec<[V; 1]>,
+}
+
+impl<'a, 'b, V> DeserializeSeed<'a> for ValuesSeed<'b, V>
+where
+    V: Deserialize<'a>,
+{
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'a>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'a, 'b, V> Visitor<'a> for ValuesSeed<'b, V>
+where
+    V: Deserialize<'a>,
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence of values")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
+    where
+        A: SeqAccess<'a>,
+    {
+        self.values
+            .reserve(cmp::min(seq.size_hint().unwrap_or(0), 4096));
+        while let Some(value) = seq.next_element()? {
+            self.values.push(value);
+        }
+        Ok(())
+    }
+}
+
 impl<'a, K, V, S> Deserialize<'a> for MultiMap<K, V, S>
 where
     K: Deserialize<'a> + Eq + Hash,
@@ -89,25 +160,665 @@ where
     }
 }
 
+/// Serde adapter which uses the same format as the `Serialize` and
+/// `Deserialize` implementations of MultiMap, but rejects input in which a key
+/// occurs more than once or has an empty sequence of values.
+///
+/// By default a repeated key is merged with the earlier occurrences and an
+/// empty sequence creates a key without values. Use this adapter where such
+/// input indicates a mistake, for example in configuration files.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate serde_derive;
+/// extern crate multimap;
+/// extern crate serde_test;
+///
+/// use multimap::MultiMap;
+/// use serde_test::{assert_de_tokens_error, assert_tokens, Token};
+///
+/// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// struct Config {
+///     #[serde(with = "multimap::serde::strict")]
+///     routes: MultiMap<String, String>,
+/// }
+///
+/// fn main() {
+///     let mut routes = MultiMap::new();
+///     routes.insert("/".to_string(), "index".to_string());
+///     assert_tokens(
+///         &Config { routes },
+///         &[
+///             Token::Struct { name: "Config", len: 1 },
+///             Token::Str("routes"),
+///             Token::Map { len: Some(1) },
+///             Token::Str("/"),
+///             Token::Seq { len: Some(1) },
+///             Token::Str("index"),
+///             Token::SeqEnd,
+///             Token::MapEnd,
+///             Token::StructEnd,
+///         ],
+///     );
+///
+///     assert_de_tokens_error::<Config>(
+///         &[
+///             Token::Struct { name: "Config", len: 1 },
+///             Token::Str("routes"),
+///             Token::Map { len: Some(2) },
+///             Token::Str("/"),
+///             Token::Seq { len: Some(1) },
+///             Token::Str("index"),
+///             Token::SeqEnd,
+///             Token::Str("/"),
+///         ],
+///         "duplicate key in multimap",
+///     );
+/// }
+/// ```
+pub mod strict {
+    use core::hash::{BuildHasher, Hash};
+
+    use super::serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use super::MultiMapVisitor;
+
+    use MultiMap;
+
+    /// Serializes the map as a map of sequences of values.
+    pub fn serialize<K, V, BS, S>(
+        map: &MultiMap<K, V, BS>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize + Eq + Hash,
+        V: Serialize,
+        BS: BuildHasher,
+        S: Serializer,
+    {
+        map.serialize(serializer)
+    }
+
+    /// Deserializes the map from a map of non-empty sequences of values with
+    /// unique keys.
+    pub fn deserialize<'a, K, V, BS, D>(deserializer: D) -> Result<MultiMap<K, V, BS>, D::Error>
+    where
+        K: Deserialize<'a> + Eq + Hash,
+        V: Deserialize<'a>,
+        BS: BuildHasher + Default,
+        D: Deserializer<'a>,
+    {
+        deserializer.deserialize_map(MultiMapVisitor::strict())
+    }
+}
+
+/// Serde adapter which represents a MultiMap as a sequence of key-value pairs,
+/// such as `[["a", 1], ["a", 2], ["b", 3]]`.
+///
+/// Every value is serialized as its own pair. The pairs of a key are written in
+/// the order of the key's values, and deserialized pairs are appended to their
+/// key's values in the order they are read.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate serde_derive;
+/// extern crate multimap;
+/// extern crate serde_test;
+///
+/// use multimap::MultiMap;
+/// use serde_test::{assert_tokens, Token};
+///
+/// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// struct Log {
+///     #[serde(with = "multimap::serde::pairs")]
+///     events: MultiMap<String, String>,
+/// }
+///
+/// fn main() {
+///     let mut events = MultiMap::new();
+///     events.insert("disk".to_string(), "full".to_string());
+///     events.insert("disk".to_string(), "ok".to_string());
+///     assert_tokens(
+///         &Log { events },
+///         &[
+///             Token::Struct { name: "Log", len: 1 },
+///             Token::Str("events"),
+///             Token::Seq { len: Some(2) },
+///             Token::Tuple { len: 2 },
+///             Token::Str("disk"),
+///             Token::Str("full"),
+///             Token::TupleEnd,
+///             Token::Tuple { len: 2 },
+///             Token::Str("disk"),
+///             Token::Str("ok"),
+///             Token::TupleEnd,
+///             Token::SeqEnd,
+///             Token::StructEnd,
+///         ],
+///     );
+/// }
+/// ```
+pub mod pairs {
+    use core::cmp;
+    use core::fmt;
+    use core::hash::{BuildHasher, Hash};
+    use core::marker::PhantomData;
+
+    use super::serde::de::{SeqAccess, Visitor};
+    use super::serde::ser::SerializeSeq;
+    use super::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use MultiMap;
+
+    /// Serializes the map as a sequence of key-value pairs.
+    pub fn serialize<K, V, BS, S>(
+        map: &MultiMap<K, V, BS>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize + Eq + Hash,
+        V: Serialize,
+        BS: BuildHasher,
+        S: Serializer,
+    {
+        let len = map.iter_all().map(|(_, values)| values.len()).sum();
+        let mut seq = serializer.serialize_seq(Some(len))?;
+        for (k, values) in map.iter_all() {
+            for v in values {
+                seq.serialize_element(&(k, v))?;
+            }
+        }
+        seq.end()
+    }
+
+    /// Deserializes the map from a sequence of key-value pairs.
+    pub fn deserialize<'a, K, V, BS, D>(deserializer: D) -> Result<MultiMap<K, V, BS>, D::Error>
+    where
+        K: Deserialize<'a> + Eq + Hash,
+        V: Deserialize<'a>,
+        BS: BuildHasher + Default,
+        D: Deserializer<'a>,
+    {
+        deserializer.deserialize_seq(PairsVisitor {
+            marker: PhantomData,
+        })
+    }
+
+    struct PairsVisitor<K, V, S> {
+        marker: PhantomData<MultiMap<K, V, S>>,
+    }
+
+    impl<'a, K, V, S> Visitor<'a> for PairsVisitor<K, V, S>
+    where
+        K: Deserialize<'a> + Eq + Hash,
+        V: Deserialize<'a>,
+        S: BuildHasher + Default,
+    {
+        type Value = MultiMap<K, V, S>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a sequence of key-value pairs")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'a>,
+        {
+            let capacity = cmp::min(seq.size_hint().unwrap_or(0), 4096);
+            let mut map = MultiMap::with_capacity_and_hasher(capacity, S::default());
+
+            while let Some((k, v)) = seq.next_element()? {
+                map.insert(k, v);
+            }
+
+            Ok(map)
+        }
+    }
+}
+
+/// Serde adapter which represents a MultiMap as a map in which a key is
+/// repeated once for each of its values, such as `{"a": 1, "a": 2, "b": 3}`.
+///
+/// The entries of a key are written in the order of the key's values, and
+/// deserialized entries are appended to their key's values in the order they
+/// are read. This matches how query strings and some log formats encode
+/// multiple values, but note that many formats and tools do not preserve
+/// repeated keys.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate serde_derive;
+/// extern crate multimap;
+/// extern crate serde_test;
+///
+/// use multimap::MultiMap;
+/// use serde_test::{assert_tokens, Token};
+///
+/// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// struct Query {
+///     #[serde(with = "multimap::serde::repeated_keys")]
+///     params: MultiMap<String, String>,
+/// }
+///
+/// fn main() {
+///     let mut params = MultiMap::new();
+///     params.insert("tag".to_string(), "a".to_string());
+///     params.insert("tag".to_string(), "b".to_string());
+///     assert_tokens(
+///         &Query { params },
+///         &[
+///             Token::Struct { name: "Query", len: 1 },
+///             Token::Str("params"),
+///             Token::Map { len: Some(2) },
+///             Token::Str("tag"),
+///             Token::Str("a"),
+///             Token::Str("tag"),
+///             Token::Str("b"),
+///             Token::MapEnd,
+///             Token::StructEnd,
+///         ],
+///     );
+/// }
+/// ```
+pub mod repeated_keys {
+    use core::cmp;
+    use core::fmt;
+    use core::hash::{BuildHasher, Hash};
+    use core::marker::PhantomData;
+
+    use super::serde::de::{MapAccess, Visitor};
+    use super::serde::ser::SerializeMap;
+    use super::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use MultiMap;
+
+    /// Serializes the map as a map with one entry per value.
+    pub fn serialize<K, V, BS, S>(
+        map: &MultiMap<K, V, BS>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize + Eq + Hash,
+        V: Serialize,
+        BS: BuildHasher,
+        S: Serializer,
+    {
+        let len = map.iter_all().map(|(_, values)| values.len()).sum();
+        let mut entries = serializer.serialize_map(Some(len))?;
+        for (k, values) in map.iter_all() {
+            for v in values {
+                entries.serialize_entry(k, v)?;
+            }
+        }
+        entries.end()
+    }
+
+    /// Deserializes the map from a map with one entry per value.
+    pub fn deserialize<'a, K, V, BS, D>(deserializer: D) -> Result<MultiMap<K, V, BS>, D::Error>
+    where
+        K: Deserialize<'a> + Eq + Hash,
+        V: Deserialize<'a>,
+        BS: BuildHasher + Default,
+        D: Deserializer<'a>,
+    {
+        deserializer.deserialize_map(RepeatedKeysVisitor {
+            marker: PhantomData,
+        })
+    }
+
+    struct RepeatedKeysVisitor<K, V, S> {
+        marker: PhantomData<MultiMap<K, V, S>>,
+    }
+
+    impl<'a, K, V, S> Visitor<'a> for RepeatedKeysVisitor<K, V, S>
+    where
+        K: Deserialize<'a> + Eq + Hash,
+        V: Deserialize<'a>,
+        S: BuildHasher + Default,
+    {
+        type Value = MultiMap<K, V, S>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a map with repeated keys")
+        }
+
+        fn visit_map<A>(self, mut entries: A) -> Result<Self::Value, A::Error>
+        where
+            A: MapAccess<'a>,
+        {
+            let capacity = cmp::min(entries.size_hint().unwrap_or(0), 4096);
+            let mut map = MultiMap::with_capacity_and_hasher(capacity, S::default());
+
+            while let Some((k, v)) = entries.next_entry()? {
+                map.insert(k, v);
+            }
+
+            Ok(map)
+        }
+    }
+}
+
+/// Serde adapter which writes keys with exactly one value as a scalar and all
+/// other keys as a sequence, such as `{"a": 1, "b": [2, 3]}`.
+///
+/// This matches the distinction made by `MultiMap::is_vec` and is convenient for
+/// human-edited configuration files. Deserialization accepts either shape for
+/// every key and reads the values straight into the map's storage. Because a
+/// scalar and a sequence can only be told apart by looking at the input, this
+/// adapter requires a self-describing format such as JSON or YAML.
+///
+/// Values that are themselves sequences cannot be represented unambiguously
+/// and should not be used with this adapter.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate serde_derive;
+/// extern crate multimap;
+/// extern crate serde_test;
+///
+/// use multimap::MultiMap;
+/// use serde_test::{assert_de_tokens, Token};
+///
+/// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// struct Config {
+///     #[serde(with = "multimap::serde::one_or_many")]
+///     upstreams: MultiMap<String, String>,
+/// }
+///
+/// fn main() {
+///     let mut upstreams = MultiMap::new();
+///     upstreams.insert("api".to_string(), "10.0.0.1".to_string());
+///     upstreams.insert("web".to_string(), "10.0.0.2".to_string());
+///     upstreams.insert("web".to_string(), "10.0.0.3".to_string());
+///     assert_de_tokens(
+///         &Config { upstreams },
+///         &[
+///             Token::Struct { name: "Config", len: 1 },
+///             Token::Str("upstreams"),
+///             Token::Map { len: Some(2) },
+///             Token::Str("api"),
+///             Token::Str("10.0.0.1"),
+///             Token::Str("web"),
+///             Token::Seq { len: Some(2) },
+///             Token::Str("10.0.0.2"),
+///             Token::Str("10.0.0.3"),
+///             Token::SeqEnd,
+///             Token::MapEnd,
+///             Token::StructEnd,
+///         ],
+///     );
+/// }
+/// ```
+pub mod one_or_many {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use core::cmp;
+    use core::fmt;
+    use core::hash::{BuildHasher, Hash};
+    use core::marker::PhantomData;
+
+    use super::serde::de::value::{
+        BoolDeserializer, BorrowedBytesDeserializer, BorrowedStrDeserializer, BytesDeserializer,
+        CharDeserializer, EnumAccessDeserializer, F32Deserializer, F64Deserializer,
+        I128Deserializer, I16Deserializer, I32Deserializer, I64Deserializer, I8Deserializer,
+        MapAccessDeserializer, StrDeserializer, StringDeserializer, U128Deserializer,
+        U16Deserializer, U32Deserializer, U64Deserializer, U8Deserializer, UnitDeserializer,
+    };
+    use super::serde::de::{
+        DeserializeSeed, EnumAccess, Error, IntoDeserializer, MapAccess, SeqAccess, Visitor,
+    };
+    use super::serde::ser::SerializeMap;
+    use super::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use smallvec::SmallVec;
+
+    use MultiMap;
+
+    /// Serializes the map, writing single values as scalars.
+    pub fn serialize<K, V, BS, S>(
+        map: &MultiMap<K, V, BS>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize + Eq + Hash,
+        V: Serialize,
+        BS: BuildHasher,
+        S: Serializer,
+    {
+        let mut entries = serializer.serialize_map(Some(map.len()))?;
+        for (k, values) in map.iter_all() {
+            entries.serialize_entry(k, &OneOrMany(values))?;
+        }
+        entries.end()
+    }
+
+    /// Deserializes the map, accepting either a scalar or a sequence for every key.
+    pub fn deserialize<'a, K, V, BS, D>(deserializer: D) -> Result<MultiMap<K, V, BS>, D::Error>
+    where
+        K: Deserialize<'a> + Eq + Hash,
+        V: Deserialize<'a>,
+        BS: BuildHasher + Default,
+        D: Deserializer<'a>,
+    {
+        deserializer.deserialize_map(OneOrManyMapVisitor {
+            marker: PhantomData,
+        })
+    }
+
+    struct OneOrMany<'b, V: 'b>(&'b [V]);
+
+    impl<'b, V> Serialize for OneOrMany<'b, V>
+    where
+        V: Serialize,
+    {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            match self.0 {
+                [value] => value.serialize(serializer),
+                values => serializer.collect_seq(values),
+            }
+        }
+    }
+
+    struct OneOrManyMapVisitor<K, V, S> {
+        marker: PhantomData<MultiMap<K, V, S>>,
+    }
+
+    impl<'a, K, V, S> Visitor<'a> for OneOrManyMapVisitor<K, V, S>
+    where
+        K: Deserialize<'a> + Eq + Hash,
+        V: Deserialize<'a>,
+        S: BuildHasher + Default,
+    {
+        type Value = MultiMap<K, V, S>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a map of values or sequences of values")
+        }
+
+        fn visit_map<A>(self, mut entries: A) -> Result<Self::Value, A::Error>
+        where
+            A: MapAccess<'a>,
+        {
+            let capacity = cmp::min(entries.size_hint().unwrap_or(0), 4096);
+            let mut map = MultiMap::with_capacity_and_hasher(capacity, S::default());
+
+            while let Some(key) = entries.next_key()? {
+                let values = map.inner.entry(key).or_insert_with(SmallVec::new);
+                entries.next_value_seed(OneOrManySeed { values })?;
+            }
+
+            Ok(map)
+        }
+    }
+
+    /// Appends either a single value or a sequence of values to `values`.
+    struct OneOrManySeed<'b, V: 'b> {
+        values: &'b mut SmallVec<[V; 1]>,
+    }
+
+    impl<'a, 'b, V> DeserializeSeed<'a> for OneOrManySeed<'b, V>
+    where
+        V: Deserialize<'a>,
+    {
+        type Value = ();
+
+        fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+        where
+            D: Deserializer<'a>,
+        {
+            deserializer.deserialize_any(self)
+        }
+    }
+
+    macro_rules! forward_scalar {
+        ($($method:ident($ty:ty) => $de:ident,)*) => {
+            $(
+                fn $method<E>(self, v: $ty) -> Result<(), E>
+                where
+                    E: Error,
+                {
+                    self.push($de::new(v))
+                }
+            )*
+        };
+    }
+
+    impl<'b, V> OneOrManySeed<'b, V> {
+        fn push<'a, D>(self, deserializer: D) -> Result<(), D::Error>
+        where
+            V: Deserialize<'a>,
+            D: Deserializer<'a>,
+        {
+            self.values.push(V::deserialize(deserializer)?);
+            Ok(())
+        }
+    }
+
+    impl<'a, 'b, V> Visitor<'a> for OneOrManySeed<'b, V>
+    where
+        V: Deserialize<'a>,
+    {
+        type Value = ();
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a value or a sequence of values")
+        }
+
+        forward_scalar! {
+            visit_bool(bool) => BoolDeserializer,
+            visit_i8(i8) => I8Deserializer,
+            visit_i16(i16) => I16Deserializer,
+            visit_i32(i32) => I32Deserializer,
+            visit_i64(i64) => I64Deserializer,
+            visit_i128(i128) => I128Deserializer,
+            visit_u8(u8) => U8Deserializer,
+            visit_u16(u16) => U16Deserializer,
+            visit_u32(u32) => U32Deserializer,
+            visit_u64(u64) => U64Deserializer,
+            visit_u128(u128) => U128Deserializer,
+            visit_f32(f32) => F32Deserializer,
+            visit_f64(f64) => F64Deserializer,
+            visit_char(char) => CharDeserializer,
+            visit_str(&str) => StrDeserializer,
+            visit_borrowed_str(&'a str) => BorrowedStrDeserializer,
+            visit_string(String) => StringDeserializer,
+            visit_bytes(&[u8]) => BytesDeserializer,
+            visit_borrowed_bytes(&'a [u8]) => BorrowedBytesDeserializer,
+        }
+
+        fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<(), E>
+        where
+            E: Error,
+        {
+            self.push(v.into_deserializer())
+        }
+
+        fn visit_unit<E>(self) -> Result<(), E>
+        where
+            E: Error,
+        {
+            self.push(UnitDeserializer::new())
+        }
+
+        fn visit_none<E>(self) -> Result<(), E>
+        where
+            E: Error,
+        {
+            self.push(UnitDeserializer::new())
+        }
+
+        fn visit_some<D>(self, deserializer: D) -> Result<(), D::Error>
+        where
+            D: Deserializer<'a>,
+        {
+            self.push(deserializer)
+        }
+
+        fn visit_newtype_struct<D>(self, deserializer: D) -> Result<(), D::Error>
+        where
+            D: Deserializer<'a>,
+        {
+            self.push(deserializer)
+        }
+
+        fn visit_map<A>(self, map: A) -> Result<(), A::Error>
+        where
+            A: MapAccess<'a>,
+        {
+            self.push(MapAccessDeserializer::new(map))
+        }
+
+        fn visit_enum<A>(self, data: A) -> Result<(), A::Error>
+        where
+            A: EnumAccess<'a>,
+        {
+            self.push(EnumAccessDeserializer::new(data))
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
+        where
+            A: SeqAccess<'a>,
+        {
+            self.values
+                .reserve(cmp::min(seq.size_hint().unwrap_or(0), 4096));
+            while let Some(value) = seq.next_element()? {
+                self.values.push(value);
+            }
+            Ok(())
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     extern crate serde_test;
 
-    use self::serde_test::{assert_tokens, Token};
+    use self::serde_test::{assert_de_tokens, assert_de_tokens_error, assert_tokens, Token};
 
     use super::*;
+    use test_util::*;
 
     #[test]
     fn test_empty() {
-        let map = MultiMap::<char, u8>::new();
+        let map = TestMap::<char, u8>::default();
 
         assert_tokens(&map, &[Token::Map { len: Some(0) }, Token::MapEnd]);
     }
 
     #[test]
     fn test_single() {
-        let mut map = MultiMap::<char, u8>::new();
+        let mut map = TestMap::<char, u8>::default();
         map.insert('x', 1);
 
         assert_tokens(
@@ -125,7 +836,7 @@ mod tests {
 
     #[test]
     fn test_multiple() {
-        let mut map = MultiMap::<char, u8>::new();
+        let mut map = TestMap::<char, u8>::default();
         map.insert('x', 1);
         map.insert('x', 3);
         map.insert('x', 1);
@@ -146,4 +857,295 @@ mod tests {
             ],
         );
     }
+
+    #[test]
+    fn test_duplicate_keys_merged() {
+        let mut map = TestMap::<char, u8>::default();
+        map.insert('x', 1);
+        map.insert('x', 3);
+        map.insert('y', 2);
+
+        assert_de_tokens(
+            &map,
+            &[
+                Token::Map { len: None },
+                Token::Char('x'),
+                Token::Seq { len: Some(1) },
+                Token::U8(1),
+                Token::SeqEnd,
+                Token::Char('y'),
+                Token::Seq { len: Some(1) },
+                Token::U8(2),
+                Token::SeqEnd,
+                Token::Char('x'),
+                Token::Seq { len: None },
+                Token::U8(3),
+                Token::SeqEnd,
+                Token::MapEnd,
+            ],
+        );
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Strict(TestMap<char, u8>);
+
+    impl<'a> Deserialize<'a> for Strict {
+        fn deserialize<D: Deserializer<'a>>(deserializer: D) -> Result<Self, D::Error> {
+            strict::deserialize(deserializer).map(Strict)
+        }
+    }
+
+    #[test]
+    fn test_strict() {
+        let mut map = TestMap::<char, u8>::default();
+        map.insert('x', 1);
+        map.insert('x', 3);
+
+        assert_de_tokens(
+            &Strict(map),
+            &[
+                Token::Map { len: Some(1) },
+                Token::Char('x'),
+                Token::Seq { len: Some(2) },
+                Token::U8(1),
+                Token::U8(3),
+                Token::SeqEnd,
+                Token::MapEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_strict_duplicate_key() {
+        assert_de_tokens_error::<Strict>(
+            &[
+                Token::Map { len: Some(2) },
+                Token::Char('x'),
+                Token::Seq { len: Some(1) },
+                Token::U8(1),
+                Token::SeqEnd,
+                Token::Char('x'),
+            ],
+            "duplicate key in multimap",
+        );
+    }
+
+    #[test]
+    fn test_strict_empty_values() {
+        assert_de_tokens_error::<Strict>(
+            &[
+                Token::Map { len: Some(1) },
+                Token::Char('x'),
+                Token::Seq { len: Some(0) },
+                Token::SeqEnd,
+            ],
+            "invalid length 0, expected a non-empty sequence of values",
+        );
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Pairs(TestMap<char, u8>);
+
+    impl Serialize for Pairs {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            pairs::serialize(&self.0, serializer)
+        }
+    }
+
+    impl<'a> Deserialize<'a> for Pairs {
+        fn deserialize<D: Deserializer<'a>>(deserializer: D) -> Result<Self, D::Error> {
+            pairs::deserialize(deserializer).map(Pairs)
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct RepeatedKeys(TestMap<char, u8>);
+
+    impl Serialize for RepeatedKeys {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            repeated_keys::serialize(&self.0, serializer)
+        }
+    }
+
+    impl<'a> Deserialize<'a> for RepeatedKeys {
+        fn deserialize<D: Deserializer<'a>>(deserializer: D) -> Result<Self, D::Error> {
+            repeated_keys::deserialize(deserializer).map(RepeatedKeys)
+        }
+    }
+
+    #[test]
+    fn test_pairs() {
+        let mut map = TestMap::<char, u8>::default();
+        map.insert('x', 1);
+        map.insert('x', 3);
+
+        assert_tokens(
+            &Pairs(map),
+            &[
+                Token::Seq { len: Some(2) },
+                Token::Tuple { len: 2 },
+                Token::Char('x'),
+                Token::U8(1),
+                Token::TupleEnd,
+                Token::Tuple { len: 2 },
+                Token::Char('x'),
+                Token::U8(3),
+                Token::TupleEnd,
+                Token::SeqEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_pairs_interleaved_keys() {
+        let mut map = TestMap::<char, u8>::default();
+        map.insert('x', 1);
+        map.insert('y', 2);
+        map.insert('x', 3);
+
+        assert_de_tokens(
+            &Pairs(map),
+            &[
+                Token::Seq { len: None },
+                Token::Tuple { len: 2 },
+                Token::Char('x'),
+                Token::U8(1),
+                Token::TupleEnd,
+                Token::Tuple { len: 2 },
+                Token::Char('y'),
+                Token::U8(2),
+                Token::TupleEnd,
+                Token::Tuple { len: 2 },
+                Token::Char('x'),
+                Token::U8(3),
+                Token::TupleEnd,
+                Token::SeqEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_repeated_keys() {
+        let mut map = TestMap::<char, u8>::default();
+        map.insert('x', 1);
+        map.insert('x', 3);
+
+        assert_tokens(
+            &RepeatedKeys(map),
+            &[
+                Token::Map { len: Some(2) },
+                Token::Char('x'),
+                Token::U8(1),
+                Token::Char('x'),
+                Token::U8(3),
+                Token::MapEnd,
+            ],
+        );
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct OneOrMany<K: Eq + Hash, V>(TestMap<K, V>);
+
+    impl<K: Serialize + Eq + Hash, V: Serialize> Serialize for OneOrMany<K, V> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            one_or_many::serialize(&self.0, serializer)
+        }
+    }
+
+    impl<'a, K, V> Deserialize<'a> for OneOrMany<K, V>
+    where
+        K: Deserialize<'a> + Eq + Hash,
+        V: Deserialize<'a>,
+    {
+        fn deserialize<D: Deserializer<'a>>(deserializer: D) -> Result<Self, D::Error> {
+            one_or_many::deserialize(deserializer).map(OneOrMany)
+        }
+    }
+
+    #[test]
+    fn test_one_or_many_single() {
+        let mut map = TestMap::<char, u8>::default();
+        map.insert('x', 1);
+
+        assert_tokens(
+            &OneOrMany(map),
+            &[
+                Token::Map { len: Some(1) },
+                Token::Char('x'),
+                Token::U8(1),
+                Token::MapEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_one_or_many_multiple() {
+        let mut map = TestMap::<char, u8>::default();
+        map.insert('x', 1);
+        map.insert('x', 3);
+
+        assert_tokens(
+            &OneOrMany(map),
+            &[
+                Token::Map { len: Some(1) },
+                Token::Char('x'),
+                Token::Seq { len: Some(2) },
+                Token::U8(1),
+                Token::U8(3),
+                Token::SeqEnd,
+                Token::MapEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_one_or_many_mixed() {
+        let mut map = TestMap::<char, u8>::default();
+        map.insert('x', 1);
+        map.insert('y', 2);
+        map.insert('y', 3);
+        map.insert('x', 4);
+
+        assert_de_tokens(
+            &OneOrMany(map),
+            &[
+                Token::Map { len: None },
+                Token::Char('x'),
+                Token::U8(1),
+                Token::Char('y'),
+                Token::Seq { len: None },
+                Token::U8(2),
+                Token::U8(3),
+                Token::SeqEnd,
+                Token::Char('x'),
+                Token::Seq { len: Some(1) },
+                Token::U8(4),
+                Token::SeqEnd,
+                Token::MapEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_one_or_many_string_values() {
+        let mut map = TestMap::<String, String>::default();
+        map.insert("a".to_string(), "one".to_string());
+        map.insert("b".to_string(), "two".to_string());
+        map.insert("b".to_string(), "three".to_string());
+
+        assert_de_tokens(
+            &OneOrMany(map),
+            &[
+                Token::Map { len: Some(2) },
+                Token::Str("a"),
+                Token::BorrowedStr("one"),
+                Token::Str("b"),
+                Token::Seq { len: Some(2) },
+                Token::String("two"),
+                Token::Str("three"),
+                Token::SeqEnd,
+                Token::MapEnd,
+            ],
+        );
+    }
 }
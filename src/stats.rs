@@ -0,0 +1,144 @@
+// Copyright (c) 2016 multimap developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Memory usage statistics of the per-key value lists of a MultiMap.
+
+use alloc::vec::Vec;
+use core::mem;
+
+use smallvec::SmallVec;
+
+use MultiMap;
+
+/// Statistics about how the values of a MultiMap are stored, as returned by
+/// `MultiMap::stats`.
+///
+/// The values of each key are stored in a `SmallVec<[V; N]>`, which holds up
+/// to `N` values inline in the hash table and moves them to a heap buffer, or
+/// spills, when more are pushed. The byte counts cover the value lists only,
+/// not the keys or the hash table's own overhead.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MultiMapStats {
+    /// The number of keys.
+    pub keys: usize,
+
+    /// The total number of values.
+    pub values: usize,
+
+    /// The number of keys whose values have spilled to the heap.
+    pub spilled_keys: usize,
+
+    /// The number of keys by number of values: `length_histogram[i]` is the
+    /// number of keys with exactly `i` values. The last element is never 0,
+    /// and the histogram is empty for an empty map.
+    pub length_histogram: Vec<usize>,
+
+    /// The bytes taken by the per-key `SmallVec`s inside the hash table,
+    /// including their inline buffers.
+    pub inline_bytes: usize,
+
+    /// The bytes of the heap buffers of spilled keys, by their capacity.
+    pub heap_bytes: usize,
+
+    /// The number of allocated value slots that hold no value, in inline
+    /// buffers and heap buffers. An inline buffer of a spilled key is not
+    /// counted, since it is reused to store the heap pointer.
+    pub wasted_capacity: usize,
+}
+
+impl MultiMapStats {
+    /// Returns the total bytes taken by the value lists, inline and on the
+    /// heap.
+    pub fn total_bytes(&self) -> usize {
+        self.inline_bytes + self.heap_bytes
+    }
+}
+
+impl<K, V, S, const N: usize> MultiMap<K, V, S, N> {
+    /// Returns statistics about how the values are stored, to help choose the
+    /// inline capacity `N`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert("a", 1u32);
+    /// map.insert("b", 2);
+    /// map.insert("b", 3);
+    ///
+    /// let stats = map.stats();
+    /// assert_eq!(stats.keys, 2);
+    /// assert_eq!(stats.values, 3);
+    /// assert_eq!(stats.spilled_keys, 1);
+    /// assert_eq!(stats.length_histogram, [0, 1, 1]);
+    /// assert_eq!(stats.heap_bytes, 2 * 4);
+    /// ```
+    pub fn stats(&self) -> MultiMapStats {
+        let mut stats = MultiMapStats {
+            keys: self.inner.len(),
+            inline_bytes: self.inner.len() * mem::size_of::<SmallVec<[V; N]>>(),
+            ..MultiMapStats::default()
+        };
+
+        for values in self.inner.values() {
+            let len = values.len();
+            stats.values += len;
+            if stats.length_histogram.len() <= len {
+                stats.length_histogram.resize(len + 1, 0);
+            }
+            stats.length_histogram[len] += 1;
+
+            if values.spilled() {
+                stats.spilled_keys += 1;
+                stats.heap_bytes += values.capacity() * mem::size_of::<V>();
+            }
+            stats.wasted_capacity += values.capacity() - len;
+        }
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::*;
+
+    use hash_map::HashMap;
+
+    #[test]
+    fn empty() {
+        let map: TestMap<u8, u8> = TestMap::default();
+        assert_eq!(map.stats(), MultiMapStats::default());
+    }
+
+    #[test]
+    fn inline_and_spilled() {
+        let mut map: MultiMap<u8, u64, TestState, 2> = MultiMap {
+            inner: HashMap::default(),
+        };
+        map.inner.insert(0, SmallVec::new());
+        map.inner.insert(1, SmallVec::from_slice(&[1]));
+        map.inner.insert(2, SmallVec::from_slice(&[1, 2]));
+        let mut spilled = SmallVec::from_slice(&[1, 2, 3]);
+        spilled.reserve_exact(2);
+        map.inner.insert(3, spilled);
+
+        let stats = map.stats();
+        assert_eq!(stats.keys, 4);
+        assert_eq!(stats.values, 6);
+        assert_eq!(stats.spilled_keys, 1);
+        assert_eq!(stats.length_histogram, [1, 1, 1, 1]);
+        assert_eq!(stats.inline_bytes, 4 * mem::size_of::<SmallVec<[u64; 2]>>());
+        assert_eq!(stats.heap_bytes, 5 * 8);
+        assert_eq!(stats.wasted_capacity, 5);
+        assert_eq!(stats.total_bytes(), stats.inline_bytes + 40);
+    }
+}